@@ -1,4 +1,6 @@
 use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
 use std::vec::Vec;
 
 #[macro_use]
@@ -6,46 +8,234 @@ mod macros;
 mod cmdspec;
 mod cpu_impl;
 mod state_cache;
+mod syscall;
+mod vfs;
+mod rng;
+mod bus;
+mod devices;
+mod config;
+mod decoded;
+mod blocks;
+mod cores;
+mod protect;
+
+pub use self::state_cache::{ExecHeader, EXEC_MAGIC, EXEC_HDRSZ};
+pub use self::syscall::{SyscallHandler, IoSyscalls, SharedStdin, SharedBuf};
+pub use self::vfs::{Vfs, Files, FILE_ERR, MAX_MEM_FILE, fmode};
+pub use self::rng::Rng;
+pub use self::bus::{Device, Bus};
+pub use self::devices::{Console, Timer, Screen};
+pub use self::config::{MachineConfig, FaultPolicy, Engine, isa, sysset};
+pub use self::cores::{Core, Cores, Schedule};
+pub use self::protect::{Protection, perm, GUARD_WORDS, MAX_VIOLATIONS};
+pub(crate) use self::decoded::Decoded;
+pub(crate) use self::blocks::Blocks;
 
 pub type Word = u32;
 pub type DWord = u64;
 
-#[derive(Clone)]
+#[derive(Clone, Copy, PartialEq)]
 pub enum CmdFormat { RM, RR, RI, JMEM }
 
-type Func = &'static Fn(&mut CpuState, &Word);
+pub type Func = Rc<dyn Fn(&mut CpuState, &Instr)>;
+
+/// Operand fields of a command word, the same ones `prs!` extracts.
+/// Handy for instructions defined outside this crate.
+pub trait Operands {
+    fn rm(&self) -> (usize, u32);
+    fn rr(&self) -> (usize, usize, u32);
+    fn ri(&self) -> (usize, u32);
+    fn jm(&self) -> u32;
+}
+
+impl Operands for Word {
+    fn rm(&self) -> (usize, u32) { prs!(RM => self) }
+    fn rr(&self) -> (usize, usize, u32) { prs!(RR => *self) }
+    fn ri(&self) -> (usize, u32) { prs!(RI => *self) }
+    fn jm(&self) -> u32 { prs!(JM => self) }
+}
+
+/// Command word with the operands of its format already extracted, this
+/// is what command functions get. Asking for another format's operands
+/// falls back to parsing `word`.
+#[derive(Clone, Copy)]
+pub struct Instr {
+    pub word: Word,
+    pub code: u8,
+    pub format: CmdFormat,
+    pub r1: usize,
+    pub r2: usize,
+    pub imm: u32
+}
+
+impl Instr {
+    pub fn decode(word: Word, format: CmdFormat) -> Instr {
+        let (r1, r2, imm) = match format {
+            CmdFormat::RM   => { let (r1, mem) = prs!(RM => word); (r1, 0, mem) }
+            CmdFormat::RR   => prs!(RR => word),
+            CmdFormat::RI   => { let (r1, imm) = prs!(RI => word); (r1, 0, imm) }
+            CmdFormat::JMEM => (0, 0, prs!(JM => word))
+        };
+        Instr{ word, code: getcode!(word), format, r1, r2, imm }
+    }
+}
+
+impl Operands for Instr {
+    fn rm(&self) -> (usize, u32) {
+        if self.format == CmdFormat::RM { (self.r1, self.imm) } else { self.word.rm() }
+    }
+    fn rr(&self) -> (usize, usize, u32) {
+        if self.format == CmdFormat::RR { (self.r1, self.r2, self.imm) } else { self.word.rr() }
+    }
+    fn ri(&self) -> (usize, u32) {
+        if self.format == CmdFormat::RI { (self.r1, self.imm) } else { self.word.ri() }
+    }
+    fn jm(&self) -> u32 {
+        if self.format == CmdFormat::JMEM { self.imm } else { self.word.jm() }
+    }
+}
+
+pub struct Cmd {
+    pub name: String,
+    pub format: CmdFormat,
+    pub func: Func,
+    pub cycles: u32
+}
 
+/// Commands indexed directly by opcode, plus a name index for the assembler.
 pub struct CmdTable {
-    code: HashMap<&'static str, (u8, CmdFormat)>,
-    func: HashMap<u8, Func>,
-    name: HashMap<u8, &'static str>
+    code: HashMap<String, (u8, CmdFormat)>,
+    cmds: Vec<Option<Cmd>>,
+    generation: u64
 }
 
 impl CmdTable {
-    pub fn insert(&mut self, name: &'static str, code: u8, format: CmdFormat, func: Func) {
-        self.code.insert(name, (code, format));
-        self.func.insert(code, func);
-        self.name.insert(code, name);
+    fn empty() -> CmdTable {
+        CmdTable{ code: HashMap::new(), cmds: (0..256).map(|_| None).collect(), generation: 0 }
+    }
+
+    /// Adds a command, replacing whatever had the same name or code.
+    /// `func` may be any closure, including ones capturing state.
+    pub fn insert<F>(&mut self, name: &str, code: u8, format: CmdFormat, func: F)
+        where F: Fn(&mut CpuState, &Instr) + 'static
+    {
+        if let Some(old) = self.cmds[code as usize].take() {
+            self.code.remove(&old.name);
+        }
+        if let Some((old, _)) = self.code.insert(name.to_owned(), (code, format)) {
+            self.cmds[old as usize] = None;
+        }
+        self.cmds[code as usize] = Some(Cmd{ name: name.to_owned(), format, func: Rc::new(func), cycles: 1 });
+        self.generation += 1;
+    }
+
+    /// Adds a new command at runtime. Unlike `insert` it refuses to replace
+    /// an existing one, use `free_code` to find an unused opcode.
+    pub fn define<F>(&mut self, name: &str, code: u8, format: CmdFormat, func: F) -> Result<(), DefineError>
+        where F: Fn(&mut CpuState, &Instr) + 'static
+    {
+        if self.code.contains_key(name) {
+            return Err(DefineError::NameTaken(name.to_owned()));
+        }
+        if self.has_code(&code) {
+            return Err(DefineError::CodeTaken(code));
+        }
+        self.insert(name, code, format, func);
+        Ok(())
+    }
+
+    pub fn free_code(&self) -> Option<u8> {
+        (0..=255).find(|code| !self.has_code(code))
+    }
+
+    /// Sets how many cycles `name` costs, every command costs 1 by default.
+    pub fn set_cycles(&mut self, name: &str, cycles: u32) {
+        let code = self.get_code(name).0;
+        if let Some(cmd) = &mut self.cmds[code as usize] {
+            cmd.cycles = cycles;
+            self.generation += 1;
+        }
+    }
+    pub fn get_cycles(&self, code: &u8) -> u32 {
+        self.get(*code).map_or(0, |cmd| cmd.cycles)
     }
 
     pub fn get_code(&self, name: &str) -> &(u8, CmdFormat) {
         self.code.get(name)
-            .expect(&format!("Bad cmd name! ({})", name)) 
+            .unwrap_or_else(|| panic!("Bad cmd name! ({})", name))
     }
     pub fn get_func(&self, code: &u8) -> &Func {
-        self.func.get(code)
-            .expect(&format!("Bad cmd code! ({})", code))
+        &self.cmd(*code).func
     }
     pub fn get_name(&self, code: &u8)  -> &str {
-        self.name.get(code)
-            .expect(&format!("Bad cmd code! ({})", code))
+        &self.cmd(*code).name
+    }
+    pub fn has_code(&self, code: &u8) -> bool {
+        self.cmds[*code as usize].is_some()
+    }
+
+    pub fn get(&self, code: u8) -> Option<&Cmd> {
+        self.cmds[code as usize].as_ref()
+    }
+    fn cmd(&self, code: u8) -> &Cmd {
+        self.get(code)
+            .unwrap_or_else(|| panic!("Bad cmd code! ({})", code))
+    }
+
+    /// Changes every time a command is inserted or its cost changes, so
+    /// decoded code can tell it is out of date.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+}
+
+/// Why `CmdTable::define` refused a command.
+#[derive(Clone, Debug, PartialEq)]
+pub enum DefineError {
+    NameTaken(String),
+    CodeTaken(u8)
+}
+
+impl fmt::Display for DefineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DefineError::NameTaken(name) => write!(f, "cmd {} is already defined", name),
+            DefineError::CodeTaken(code) => write!(f, "opcode {} is already taken", code)
+        }
     }
 }
 
+impl std::error::Error for DefineError {}
+
+impl Default for CmdTable {
+    fn default() -> CmdTable { CmdTable::new() }
+}
+
 pub const MEMSZ : usize = 1 << 20;
+/// Most memory, in words, an executable or `--mem` may ask for.
+pub const MAX_MEM_WORDS : usize = 1 << 26;
+/// Interrupt vectors live in the last `IVT_LEN` words of memory, entry `i`
+/// holds the handler address for line `i`. The stack starts below them.
+/// Their address depends on the memory size, so guests get it from
+/// `syscall rN 4` instead of hardcoding it.
+pub const IVT_LEN : Word = 16;
 
+/// Result of the last comparison. `NAN` is the state before any, `U`
+/// means unordered: `cmpd` with a NaN operand.
 #[derive(Clone, Copy)]
-pub enum Flag { NAN = 0, G = 1, E = 2, L = 3 }
+pub enum Flag { NAN = 0, G = 1, E = 2, L = 3, U = 4 }
+impl Flag {
+    pub fn from_word(val: Word) -> Flag {
+        match val {
+            1 => Flag::G,
+            2 => Flag::E,
+            3 => Flag::L,
+            4 => Flag::U,
+            _ => Flag::NAN
+        }
+    }
+}
+
 impl PartialEq for Flag {
     fn eq(&self, other: &Flag) -> bool {
         if *self as u32 == Flag::NAN as u32 || *other as u32 == Flag::NAN as u32 {
@@ -55,6 +245,90 @@ impl PartialEq for Flag {
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FaultKind {
+    DivByZero,
+    BadAddress(Word),
+    StackOverflow,
+    StackUnderflow,
+    BadRegister(usize),
+    BadOpcode(u8),
+    BadSyscall(Word),
+    BadInput,
+    HeapOverflow,
+    Protection(Word)
+}
+
+impl FaultKind {
+    /// Number pushed for the guest fault handler.
+    pub fn code(&self) -> Word {
+        match self {
+            FaultKind::DivByZero      => 1,
+            FaultKind::BadAddress(_)  => 2,
+            FaultKind::StackOverflow  => 3,
+            FaultKind::StackUnderflow => 4,
+            FaultKind::BadRegister(_) => 5,
+            FaultKind::BadOpcode(_)   => 6,
+            FaultKind::BadSyscall(_)  => 7,
+            FaultKind::BadInput       => 8,
+            FaultKind::HeapOverflow   => 9,
+            FaultKind::Protection(_)  => 10
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Fault {
+    pub kind: FaultKind,
+    pub pc: Word,
+    pub cmd: Word,
+    pub core: usize
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            FaultKind::DivByZero      => write!(f, "division by zero")?,
+            FaultKind::BadAddress(a)  => write!(f, "bad memory address {}", a)?,
+            FaultKind::StackOverflow  => write!(f, "stack overflow")?,
+            FaultKind::StackUnderflow => write!(f, "stack underflow")?,
+            FaultKind::BadRegister(r) => write!(f, "bad register r{}", r)?,
+            FaultKind::BadOpcode(c)   => write!(f, "unknown opcode {}", c)?,
+            FaultKind::BadSyscall(n)  => write!(f, "bad syscall {}", n)?,
+            FaultKind::BadInput       => write!(f, "bad syscall input")?,
+            FaultKind::HeapOverflow   => write!(f, "heap collided with the stack")?,
+            FaultKind::Protection(a)  => write!(f, "protection violation at address {}", a)?
+        }
+        write!(f, " at pc {} (cmd {:#010x})", self.pc, self.cmd)?;
+        if self.core != 0 {
+            write!(f, " on core {}", self.core)?;
+        }
+        Ok(())
+    }
+}
+
+/// A store into loaded code, recorded while `CpuState::watch_code` is on.
+#[derive(Clone, Copy, Debug)]
+pub struct CodeStore {
+    pub pc: Word,
+    pub core: usize,
+    pub adr: Word,
+    pub old: Word,
+    pub new: Word
+}
+
+/// `CpuState::code_stores` keeps only the first ones.
+pub const MAX_CODE_STORES: usize = 1000;
+
+/// How `CPU::exec` stopped. `Halted` carries the exit status.
+#[derive(Clone, Copy, Debug)]
+pub enum Outcome {
+    Halted(Word),
+    Fault(Fault),
+    /// `exec_with_limit` ran out of steps, the CPU can be resumed.
+    StepLimit
+}
+
 pub mod dbmode {
     pub const CMD: u8 = 0b001;
     pub const ARG: u8 = 0b010;
@@ -65,24 +339,81 @@ pub struct CpuState {
     pub mem: Vec<Word>,
     pub r: [Word; 16],
     pub f : Flag,
+    /// r14 of the running core with nothing pushed yet, `pop` faults there.
+    pub stack_top: Word,
     pub halt: bool,
+    pub status: Word,
     pub mode: u8,
-    pub progsz : u32
+    pub progsz : u32,
+    /// The image `[0, progsz)` is code up to `cnst_start`, then constants
+    /// up to `data_start`, then data.
+    pub cnst_start: Word,
+    pub data_start: Word,
+    pub brk: Word,
+    pub fault: Option<Fault>,
+    pub fault_handler: Option<Word>,
+    pub sysset: Word,
+    pub syscalls: Box<dyn SyscallHandler>,
+    pub files: Files,
+    pub steps: u64,
+    pub cycles: u64,
+    pub rng: Rng,
+    pub bus: Bus,
+    pub ie: bool,
+    pub irq: Word,
+    pub cores: Cores,
+    pub protection: Protection,
+    pub violations: Vec<Fault>,
+    /// Record stores into `[0, cnst_start)` in `code_stores`.
+    pub watch_code: bool,
+    pub code_stores: Vec<CodeStore>,
+    /// Ranges of the loaded image written since the block cache last looked.
+    pub(crate) code_writes: Vec<(Word, Word)>
 }
 
 impl CpuState {
-    pub fn new() -> CpuState {
-        CpuState{ mem: vec![0; MEMSZ], r: [0; 16], f : Flag::NAN, halt: false, mode: 0, progsz: 0 }
+    pub fn new(cfg: &MachineConfig) -> CpuState {
+        let fault_handler = match cfg.faults {
+            FaultPolicy::Stop => None,
+            FaultPolicy::Trap(adr) => Some(adr)
+        };
+        let mut state = CpuState{ mem: vec![0; cfg.mem_words.max(IVT_LEN as usize)], r: [0; 16], f : Flag::NAN,
+                  stack_top: 0, halt: false, status: 0, mode: 0, progsz: 0, cnst_start: 0, data_start: 0, brk: 0,
+                  fault: None, fault_handler, sysset: cfg.syscalls, syscalls: Box::new(IoSyscalls::stdio()),
+                  files: Files::new(Vfs::Closed), steps: 0, cycles: 0, rng: Rng::new(Rng::DEFAULT_SEED),
+                  bus: Bus::new(), ie: false, irq: 0, cores: Cores::new(cfg.schedule),
+                  protection: cfg.protection, violations: Vec::new(),
+                  watch_code: cfg.watch_code, code_stores: Vec::new(), code_writes: Vec::new() };
+        state.r[14] = cfg.stack.unwrap_or_else(|| state.ivt_base());
+        state.stack_top = state.r[14];
+        state
+    }
+
+    /// Changes the memory size, keeping the contents that still fit.
+    pub fn resize(&mut self, words: usize) {
+        self.mem.resize(words.max(IVT_LEN as usize), 0);
     }
 }
 
+impl Default for CpuState {
+    fn default() -> CpuState { CpuState::new(&MachineConfig::default()) }
+}
+
 pub struct CPU {
     pub state: CpuState,
-    pub table: CmdTable
+    pub table: CmdTable,
+    pub engine: Engine,
+    pub(crate) decoded: Decoded,
+    pub(crate) blocks: Blocks
 }
 
 impl CPU {
-    pub fn new() -> CPU {
-        CPU{ state: CpuState::new(), table: CmdTable::new() }
+    pub fn new(cfg: MachineConfig) -> CPU {
+        CPU{ state: CpuState::new(&cfg), table: CmdTable::with_isa(cfg.isa), engine: cfg.engine,
+             decoded: Decoded::default(), blocks: Blocks::default() }
     }
+}
+
+impl Default for CPU {
+    fn default() -> CPU { CPU::new(MachineConfig::default()) }
 }
\ No newline at end of file
@@ -0,0 +1,78 @@
+use super::*;
+
+/// Commands that end a basic block: everything that may jump or stop.
+const BLOCK_END: &[&str] = &["halt", "syscall", "jmp", "jne", "jeq", "jle", "jl", "jge", "jg",
+                             "ju", "jo", "call", "calli", "ret", "iret"];
+/// Longest block translated at once.
+const MAX_BLOCK: usize = 64;
+
+/// A command with its operands bound, ready to run.
+pub(crate) struct Op {
+    pub run: Box<dyn Fn(&mut CpuState)>,
+    pub cycles: u32
+}
+
+/// Straight-line code of the loaded image starting at `start`.
+pub(crate) struct Block {
+    pub start: Word,
+    pub ops: Vec<Op>
+}
+
+impl Block {
+    fn translate(table: &CmdTable, code: &[Word], start: Word) -> Option<Block> {
+        let mut ops = Vec::new();
+        for &word in code.iter().skip(start as usize).take(MAX_BLOCK) {
+            let cmd = match table.get(getcode!(word)) { Some(cmd) => cmd, None => break };
+            let instr = Instr::decode(word, cmd.format);
+            let func = cmd.func.clone();
+            ops.push(Op{ run: Box::new(move |cpu| func(cpu, &instr)), cycles: cmd.cycles });
+            if BLOCK_END.contains(&cmd.name.as_str()) { break; }
+        }
+        if ops.is_empty() { None } else { Some(Block{ start, ops }) }
+    }
+
+    fn end(&self) -> Word {
+        self.start + self.ops.len() as Word
+    }
+}
+
+/// Translated blocks by start address, all inside `CpuState::exec_end`.
+/// Blocks overlapping the ranges in `CpuState::code_writes` are dropped
+/// before the next lookup, redefining commands or loading another image
+/// drops all of them.
+#[derive(Default)]
+pub(crate) struct Blocks {
+    blocks: HashMap<Word, Rc<Block>>,
+    generation: u64,
+    end: u32
+}
+
+impl Blocks {
+    /// Block starting at `pc`, translated on first use. `None` outside the
+    /// loaded image or when the command at `pc` is unknown.
+    pub fn get(&mut self, table: &CmdTable, state: &mut CpuState, pc: Word) -> Option<Rc<Block>> {
+        let end = state.exec_end();
+        if self.generation != table.generation() || self.end != end {
+            self.blocks.clear();
+            self.generation = table.generation();
+            self.end = end;
+        }
+        if !state.code_writes.is_empty() {
+            let writes = &state.code_writes;
+            self.blocks.retain(|_, block| {
+                !writes.iter().any(|&(start, end)| start < block.end() && block.start < end)
+            });
+            state.code_writes.clear();
+        }
+        if pc >= end {
+            return None;
+        }
+
+        if let Some(block) = self.blocks.get(&pc) {
+            return Some(block.clone());
+        }
+        let block = Rc::new(Block::translate(table, &state.mem[..end as usize], pc)?);
+        self.blocks.insert(pc, block.clone());
+        Some(block)
+    }
+}
@@ -0,0 +1,67 @@
+use super::*;
+
+/// Something mapped into the address space. Offsets are relative to the
+/// start of the mapping and always below `size()`.
+pub trait Device {
+    fn size(&self) -> Word;
+    fn read(&mut self, off: Word) -> Word;
+    fn write(&mut self, off: Word, val: Word);
+    /// Called once per executed instruction, returns an interrupt line to raise.
+    fn tick(&mut self) -> Option<Word> { None }
+}
+
+struct Mapping {
+    start: Word,
+    size: Word,
+    dev: Box<dyn Device>
+}
+
+/// Address ranges routed to devices instead of `CpuState::mem`.
+#[derive(Default)]
+pub struct Bus {
+    maps: Vec<Mapping>
+}
+
+impl Bus {
+    pub fn new() -> Bus {
+        Bus{ maps: Vec::new() }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.maps.is_empty()
+    }
+
+    /// Maps `dev` at `[start, start + dev.size())`, ranges can't overlap or
+    /// run past the last address.
+    pub fn map(&mut self, start: Word, dev: Box<dyn Device>) {
+        let size = dev.size();
+        let end = start as u64 + size as u64;
+        if end > Word::MAX as u64 + 1 {
+            panic!("Device at {} doesn't fit the address space", start);
+        }
+        for m in &self.maps {
+            if (start as u64) < m.start as u64 + m.size as u64 && (m.start as u64) < end {
+                panic!("Device at {} overlaps the one at {}", start, m.start);
+            }
+        }
+        self.maps.push(Mapping{ start, size, dev });
+    }
+
+    /// Device mapped at `adr` and the offset into it.
+    pub fn device(&mut self, adr: Word) -> Option<(&mut (dyn Device + 'static), Word)> {
+        self.maps.iter_mut()
+            .find(|m| adr >= m.start && adr - m.start < m.size)
+            .map(|m| (m.dev.as_mut(), adr - m.start))
+    }
+
+    /// Ticks every device, returns the mask of interrupt lines they raised.
+    pub fn tick(&mut self) -> Word {
+        let mut irq = 0;
+        for m in &mut self.maps {
+            if let Some(line) = m.dev.tick() {
+                irq |= 1 << (line % IVT_LEN);
+            }
+        }
+        irq
+    }
+}
@@ -1,107 +1,142 @@
 use super::*;
-use std::io;
 
 use CmdFormat::*;
 
 impl CmdTable {
     pub fn new() -> CmdTable {
-        let mut table = CmdTable {
-            code: HashMap::new(),
-            func: HashMap::new(),
-            name: HashMap::new()
-        };
+        CmdTable::with_isa(isa::ALL)
+    }
+
+    /// Base instructions plus the `isa` extension groups set in `ext`.
+    pub fn with_isa(ext: Word) -> CmdTable {
+        let mut table = CmdTable::empty();
 
         macro_rules! insert {
             (op => $name:expr, $num:expr, $op:tt) => {{
-                table.insert($name, $num, RR, &|cpu, arg| {
-                    let (r1, r2, imm) = prs!(RR => arg);
+                table.insert($name, $num, RR, |cpu, arg| {
+                    let (r1, r2, imm) = arg.rr();
 
                     cpu.r[r1] $op cpu.r[r2].wrapping_add(imm);
                 })
             }};
             (opi => $name:expr, $num:expr, $op:tt) => {{
-                table.insert($name, $num, RI, &|cpu, arg| {
-                    let (reg, imm) = prs!(RI => arg);
+                table.insert($name, $num, RI, |cpu, arg| {
+                    let (reg, imm) = arg.ri();
 
                     cpu.r[reg] $op imm;
                 });
             }};
+            (sh => $name:expr, $num:expr, $sh:ident) => {{
+                table.insert($name, $num, RR, |cpu, arg| {
+                    let (r1, r2, imm) = arg.rr();
+
+                    cpu.r[r1] = cpu.r[r1].$sh(cpu.r[r2].wrapping_add(imm)).unwrap_or(0);
+                })
+            }};
+            (shi => $name:expr, $num:expr, $sh:ident) => {{
+                table.insert($name, $num, RI, |cpu, arg| {
+                    let (reg, imm) = arg.ri();
+
+                    cpu.r[reg] = cpu.r[reg].$sh(imm).unwrap_or(0);
+                });
+            }};
             (opd => $name:expr, $num:expr, $op:tt) => {{
-                table.insert($name, $num, RR, &|cpu, arg| {
-                    let (r1, r2, _) = prs!(RR => arg);
+                table.insert($name, $num, RR, |cpu, arg| {
+                    let (r1, r2, _) = arg.rr();
+                    if !cpu.check_pair(r1) || !cpu.check_pair(r2) { return; }
 
                     let f1 = cpu.scand(r1);
                     let f2 = cpu.scand(r2);
                     cpu.writed(f1 $op f2, r1);
                 });
             }};
-            (jmp => $name:expr, $num:expr, $rel:tt, $obj:expr) => {
-                table.insert($name, $num, JMEM, &|cpu, arg| {
-                    let mem = prs!(JM => arg);
-                    if cpu.f $rel $obj {
+            (fnd => $name:expr, $num:expr, $f:expr) => {{
+                table.insert($name, $num, RR, |cpu, arg| {
+                    let (r1, r2, _) = arg.rr();
+                    if !cpu.check_pair(r1) || !cpu.check_pair(r2) { return; }
+
+                    let res = $f(cpu.scand(r2));
+                    cpu.writed(res, r1);
+                });
+            }};
+            (bit => $name:expr, $num:expr, $f:expr) => {{
+                table.insert($name, $num, RR, |cpu, arg| {
+                    let (r1, r2, imm) = arg.rr();
+
+                    cpu.r[r1] = $f(cpu.r[r1], cpu.r[r2].wrapping_add(imm) % 32);
+                });
+            }};
+            (biti => $name:expr, $num:expr, $f:expr) => {{
+                table.insert($name, $num, RI, |cpu, arg| {
+                    let (reg, imm) = arg.ri();
+
+                    cpu.r[reg] = $f(cpu.r[reg], imm % 32);
+                });
+            }};
+            (un => $name:expr, $num:expr, $f:expr) => {{
+                table.insert($name, $num, RR, |cpu, arg| {
+                    let (r1, r2, _) = arg.rr();
+
+                    cpu.r[r1] = $f(cpu.r[r2]);
+                });
+            }};
+            (jmp => $name:expr, $num:expr, $cond:expr) => {
+                table.insert($name, $num, JMEM, |cpu, arg| {
+                    let mem = arg.jm();
+                    if $cond(cpu.f) {
                         cpu.jump(mem);
                     }
                 });
             };
         }
 
-        macro_rules! parseline {
-            ($T:ty) => {{
-                let mut in_txt = String::new();
-                io::stdin().read_line(&mut in_txt)
-                    .expect("Failed to read from stdin");
-                let in_num = in_txt.trim().parse::<$T>()
-                    .expect(&format!("STDin: expected {}, got {}", stringify!($T), in_txt));
-
-                in_num
-            }};
-        }
+        table.insert("halt", 0, RI, |cpu, _| cpu.exit(0));
+        table.insert("syscall", 1, RI, |cpu, arg| {
+            let (reg, imm) = arg.ri();
 
-        table.insert("halt", 0, RI, &|cpu, _| cpu.halt = true);
-        table.insert("syscall", 1, RI, &|cpu, arg| {
-            let (reg, imm) = prs!(RI => arg);
+            cpu.syscall(reg, imm);
+        });
 
-            match imm {
-                0 => cpu.halt = true,
-                100 => cpu.r[reg] = parseline!(u32),
-                101 => cpu.writed(parseline!(f64), reg),
-                102 => print!("{}", cpu.r[reg]),
-                103 => print!("{}", cpu.scand(reg)),
-                104 => cpu.r[reg] = parseline!(char) as u32,
-                105 => print!("{}", (cpu.r[reg] as u8) as char),
+        table.insert("add", 2, RR, |cpu, arg| {
+            let (r1, r2, imm) = arg.rr();
 
-                _ => panic!("Bad syscall argument!")
-            }
+            cpu.r[r1] = cpu.r[r1].wrapping_add(cpu.r[r2].wrapping_add(imm));
         });
-
-        insert!(op => "add", 2, +=);
-        table.insert("addi", 3, RI, &|cpu, arg| {
-            let (reg, imm) = prs!(RI => arg);
+        table.insert("addi", 3, RI, |cpu, arg| {
+            let (reg, imm) = arg.ri();
 
             cpu.r[reg] = cpu.r[reg].wrapping_add(imm);
         });
-        insert!(op => "sub", 4, -=);
-        table.insert("subi", 5, RI, &|cpu, arg| {
-            let (reg, imm) = prs!(RI => arg);
+        table.insert("sub", 4, RR, |cpu, arg| {
+            let (r1, r2, imm) = arg.rr();
+
+            cpu.r[r1] = cpu.r[r1].wrapping_sub(cpu.r[r2].wrapping_add(imm));
+        });
+        table.insert("subi", 5, RI, |cpu, arg| {
+            let (reg, imm) = arg.ri();
 
             cpu.r[reg] = cpu.r[reg].wrapping_sub(imm);
         });
-        table.insert("mul", 6, RR, &|cpu, arg| {
-            let (r1, r2, _) = prs!(RR => arg);
+        table.insert("mul", 6, RR, |cpu, arg| {
+            let (r1, r2, _) = arg.rr();
+            if !cpu.check_pair(r1) { return; }
 
             let mul: DWord = (cpu.r[r1] as u64) * (cpu.r[r2] as u64);
             cpu.r[r1] = (mul & 0b11111111111111111111111111111111) as u32;
             cpu.r[r1 + 1] = (mul >> 32) as u32;
         });
-        table.insert("muli", 7, RI, &|cpu, arg| {
-            let (reg, imm) = prs!(RI => arg);
+        table.insert("muli", 7, RI, |cpu, arg| {
+            let (reg, imm) = arg.ri();
+            if !cpu.check_pair(reg) { return; }
 
             let mul: DWord = (cpu.r[reg] as u64) * (imm as u64);
             cpu.r[reg] = (mul & 0b11111111111111111111111111111111) as u32;
             cpu.r[reg + 1] = (mul >> 32) as u32;
         });
-        table.insert("div", 8, RR, &|cpu, arg| {
-            let (r1, r2, _) = prs!(RR => arg);
+        table.insert("div", 8, RR, |cpu, arg| {
+            let (r1, r2, _) = arg.rr();
+            if !cpu.check_pair(r1) { return; }
+            if cpu.r[r2] == 0 { return cpu.fault(FaultKind::DivByZero); }
 
             let q: u32 = cpu.r[r1] / cpu.r[r2];
             let r: u32 = cpu.r[r1] % cpu.r[r2];
@@ -109,43 +144,46 @@ impl CmdTable {
             cpu.r[r1] = q;
             cpu.r[r1 + 1] = r;
         });
-        table.insert("divi", 9, RI, &|cpu, arg| {
-            let (reg, imm) = prs!(RI => arg);
+        table.insert("divi", 9, RI, |cpu, arg| {
+            let (reg, imm) = arg.ri();
+            if !cpu.check_pair(reg) { return; }
+            if imm == 0 { return cpu.fault(FaultKind::DivByZero); }
 
             let r = cpu.r[reg] % imm;
             cpu.r[reg] /= imm;
             cpu.r[reg + 1] = r;
         });
         insert!(opi => "lc",   12, =);
-        insert!(op  => "shl",  13, <<=);
-        insert!(opi => "shli", 14, <<=);
-        insert!(op  => "shr",  15, >>=);
-        insert!(opi => "shri", 16, >>=);
+        insert!(sh  => "shl",  13, checked_shl);
+        insert!(shi => "shli", 14, checked_shl);
+        insert!(sh  => "shr",  15, checked_shr);
+        insert!(shi => "shri", 16, checked_shr);
         insert!(op  => "and",  17, &=);
         insert!(opi => "andi", 18, &=);
         insert!(op  => "or",   19, |=);
         insert!(opi => "ori",  20, |=);
         insert!(op  => "xor",  21, ^=);
         insert!(opi => "xori", 22, ^=);
-        table.insert("not", 23, RI, &|cpu, arg| {
-            let (reg, _) = prs!(RI => arg);
+        table.insert("not", 23, RI, |cpu, arg| {
+            let (reg, _) = arg.ri();
 
             cpu.r[reg] = !cpu.r[reg];
         });
         insert!(op  => "mov",  24, =);
-
         insert!(opd => "addd", 32, +);
         insert!(opd => "subd", 33, -);
         insert!(opd => "muld", 34, *);
         insert!(opd => "divd", 35, /);
         
-        table.insert("itod", 36, RR, &|cpu, arg| {
-            let (r1, r2, _) = prs!(RR => arg);
+        table.insert("itod", 36, RR, |cpu, arg| {
+            let (r1, r2, _) = arg.rr();
+            if !cpu.check_pair(r1) { return; }
 
             cpu.writed(cpu.r[r2] as f64, r1);
         });
-        table.insert("dtoi", 37, RR, &|cpu, arg| {
-            let (r1, r2, _) = prs!(RR => arg);
+        table.insert("dtoi", 37, RR, |cpu, arg| {
+            let (r1, r2, _) = arg.rr();
+            if !cpu.check_pair(r2) { return; }
 
             let src = cpu.scand(r2).trunc();
             let res;
@@ -155,33 +193,34 @@ impl CmdTable {
             cpu.r[r1] = res;
         });
 
-        table.insert("push", 38, RI, &|cpu, arg| {
-            let (reg, imm) = prs!(RI => arg);
-            cpu.push(cpu.r[reg] + imm);
+        table.insert("push", 38, RI, |cpu, arg| {
+            let (reg, imm) = arg.ri();
+            cpu.push(cpu.r[reg].wrapping_add(imm));
         });
 
-        table.insert("pop", 39, RI, &|cpu, arg| {
-            let (reg, imm) = prs!(RI => arg);
-            cpu.r[reg] = cpu.pop() + imm;
+        table.insert("pop", 39, RI, |cpu, arg| {
+            let (reg, imm) = arg.ri();
+            let val = cpu.pop();
+            if cpu.fault.is_none() { cpu.r[reg] = val.wrapping_add(imm); }
         });
 
-        table.insert("call", 40, RR, &|cpu, arg| {
-            let (r1, r2, imm) = prs!(RR => arg);
+        table.insert("call", 40, RR, |cpu, arg| {
+            let (r1, r2, imm) = arg.rr();
 
             cpu.push(cpu.r[15]);
             cpu.r[r1] = cpu.r[15];
-            cpu.jump(cpu.r[r2] + imm);
+            cpu.jump(cpu.r[r2].wrapping_add(imm));
         });
 
-        table.insert("calli", 41, JMEM, &|cpu, arg| {
-            let adr = prs!(JM => arg);
+        table.insert("calli", 41, JMEM, |cpu, arg| {
+            let adr = arg.jm();
 
-            cpu.push(cpu.r[15] + 1);
+            cpu.push(cpu.r[15].wrapping_add(1));
             cpu.jump(adr);
         });
 
-        table.insert("ret", 42, JMEM, &|cpu, arg| {
-            let lay = prs!(JM => arg);
+        table.insert("ret", 42, JMEM, |cpu, arg| {
+            let lay = arg.jm();
 
             let ret_adr = cpu.pop();
             for _ in 0..lay {
@@ -191,84 +230,292 @@ impl CmdTable {
             cpu.jump(ret_adr);
         });
 
-        table.insert("cmp", 43, RR, &|cpu, arg| {
-            let (r1, r2, _) = prs!(RR => arg);
+        table.insert("cmp", 43, RR, |cpu, arg| {
+            let (r1, r2, _) = arg.rr();
 
             cpu.cmp(cpu.r[r1], cpu.r[r2]);
         });
-        table.insert("cmpi", 44, RI, &|cpu, arg| {
-            let (reg, imm) = prs!(RI => arg);
+        table.insert("cmpi", 44, RI, |cpu, arg| {
+            let (reg, imm) = arg.ri();
 
             cpu.cmp(cpu.r[reg], imm);
         });
-        table.insert("cmpd", 45, RR, &|cpu, arg| {
-            let (r1, r2, _) = prs!(RR => arg);
+        table.insert("cmpd", 45, RR, |cpu, arg| {
+            let (r1, r2, _) = arg.rr();
+            if !cpu.check_pair(r1) || !cpu.check_pair(r2) { return; }
 
             cpu.cmp(cpu.scand(r1), cpu.scand(r2));
         });
         
-        insert!(jmp => "jmp", 46, !=, Flag::NAN);
-        insert!(jmp => "jne", 47, !=, Flag::E);
-        insert!(jmp => "jeq", 48, ==, Flag::E);
-        insert!(jmp => "jle", 49, !=, Flag::G);
-        insert!(jmp => "jl",  50, ==, Flag::L);
-        insert!(jmp => "jge", 51, !=, Flag::L);
-        insert!(jmp => "jg",  52, ==, Flag::G);
-
-        table.insert("load", 64, RM, &|cpu, arg| {
-            let (reg, mem) = prs!(RM => arg);
+        // Unordered compares only satisfy jne, like NaN comparisons in C.
+        insert!(jmp => "jmp", 46, |_| true);
+        insert!(jmp => "jne", 47, |f| f != Flag::E);
+        insert!(jmp => "jeq", 48, |f| f == Flag::E);
+        insert!(jmp => "jle", 49, |f| f != Flag::G && f != Flag::U);
+        insert!(jmp => "jl",  50, |f| f == Flag::L);
+        insert!(jmp => "jge", 51, |f| f != Flag::L && f != Flag::U);
+        insert!(jmp => "jg",  52, |f| f == Flag::G);
+        insert!(jmp => "ju",  53, |f| f == Flag::U);
+        insert!(jmp => "jo",  54, |f| f == Flag::G || f == Flag::E || f == Flag::L);
+
+        table.insert("getf", 59, RI, |cpu, arg| {
+            let (reg, _) = arg.ri();
+            cpu.r[reg] = cpu.f as Word;
+        });
+        table.insert("setf", 60, RI, |cpu, arg| {
+            let (reg, imm) = arg.ri();
+            cpu.f = Flag::from_word(cpu.r[reg].wrapping_add(imm));
+        });
+
+        table.insert("load", 64, RM, |cpu, arg| {
+            let (reg, mem) = arg.rm();
             cpu.load(mem, reg);
         });
 
-        table.insert("store", 65, RM, &|cpu, arg| {
-            let (reg, mem) = prs!(RM => arg);
+        table.insert("store", 65, RM, |cpu, arg| {
+            let (reg, mem) = arg.rm();
             cpu.store(reg, mem);
         });
 
-        table.insert("load2", 66, RM, &|cpu, arg| {
-            let (reg, mem) = prs!(RM => arg);
+        table.insert("load2", 66, RM, |cpu, arg| {
+            let (reg, mem) = arg.rm();
+            if !cpu.check_pair(reg) { return; }
             cpu.load(mem, reg);
             cpu.load(mem + 1, reg + 1);
         });
 
-        table.insert("store2", 67, RM, &|cpu, arg| {
-            let (reg, mem) = prs!(RM => arg);
+        table.insert("store2", 67, RM, |cpu, arg| {
+            let (reg, mem) = arg.rm();
+            if !cpu.check_pair(reg) { return; }
             cpu.store(reg, mem);
             cpu.store(reg + 1, mem + 1);
         });
 
-        table.insert("loadr", 68, RR, &|cpu, arg| {
-            let (r1, r2, imm) = prs!(RR => arg);
-            cpu.load(cpu.r[r2] + imm, r1);
+        table.insert("loadr", 68, RR, |cpu, arg| {
+            let (r1, r2, imm) = arg.rr();
+            cpu.load(cpu.r[r2].wrapping_add(imm), r1);
         });
 
-        table.insert("storer", 69, RR, &|cpu, arg| {
-            let (r1, r2, imm) = prs!(RR => arg);
-            cpu.store(r1, cpu.r[r2] + imm);
+        table.insert("storer", 69, RR, |cpu, arg| {
+            let (r1, r2, imm) = arg.rr();
+            cpu.store(r1, cpu.r[r2].wrapping_add(imm));
         });
 
-        table.insert("loadr2", 70, RR, &|cpu, arg| {
-            let (r1, r2, imm) = prs!(RR => arg);
-            cpu.load(cpu.r[r2] + imm, r1);
-            cpu.load(cpu.r[r2] + imm + 1, r1 + 1);
+        table.insert("loadr2", 70, RR, |cpu, arg| {
+            let (r1, r2, imm) = arg.rr();
+            if !cpu.check_pair(r1) { return; }
+            let adr = cpu.r[r2].wrapping_add(imm);
+            cpu.load(adr, r1);
+            cpu.load(adr.wrapping_add(1), r1 + 1);
         });
 
-        table.insert("storer2", 71, RR, &|cpu, arg| {
-            let (r1, r2, imm) = prs!(RR => arg);
-            cpu.store(r1, cpu.r[r2] + imm);
-            cpu.store(r1 + 1, cpu.r[r2] + imm + 1);
+        table.insert("storer2", 71, RR, |cpu, arg| {
+            let (r1, r2, imm) = arg.rr();
+            if !cpu.check_pair(r1) { return; }
+            let adr = cpu.r[r2].wrapping_add(imm);
+            cpu.store(r1, adr);
+            cpu.store(r1 + 1, adr.wrapping_add(1));
         });
 
-        table.insert("$STACK", 255, JMEM, &|cpu, arg| {
-            let num = prs!(JM => arg);
+        // Byte and halfword access uses byte addresses, see `load_part`. The
+        // 20-bit address of the RM forms only reaches the first 2^20 bytes,
+        // the first quarter of default memory; use the `r` forms beyond that.
+        table.insert("loadb", 72, RM, |cpu, arg| {
+            let (reg, adr) = arg.rm();
+            cpu.load_part(adr, 1, reg);
+        });
+        table.insert("storeb", 73, RM, |cpu, arg| {
+            let (reg, adr) = arg.rm();
+            cpu.store_part(reg, adr, 1);
+        });
+        table.insert("loadh", 74, RM, |cpu, arg| {
+            let (reg, adr) = arg.rm();
+            cpu.load_part(adr, 2, reg);
+        });
+        table.insert("storeh", 75, RM, |cpu, arg| {
+            let (reg, adr) = arg.rm();
+            cpu.store_part(reg, adr, 2);
+        });
+        table.insert("loadrb", 76, RR, |cpu, arg| {
+            let (r1, r2, imm) = arg.rr();
+            cpu.load_part(cpu.r[r2].wrapping_add(imm), 1, r1);
+        });
+        table.insert("storerb", 77, RR, |cpu, arg| {
+            let (r1, r2, imm) = arg.rr();
+            cpu.store_part(r1, cpu.r[r2].wrapping_add(imm), 1);
+        });
+        table.insert("loadrh", 78, RR, |cpu, arg| {
+            let (r1, r2, imm) = arg.rr();
+            cpu.load_part(cpu.r[r2].wrapping_add(imm), 2, r1);
+        });
+        table.insert("storerh", 79, RR, |cpu, arg| {
+            let (r1, r2, imm) = arg.rr();
+            cpu.store_part(r1, cpu.r[r2].wrapping_add(imm), 2);
+        });
+
+        // Cores switch between instructions only, so these are atomic.
+        table.insert("cas", 108, RR, |cpu, arg| {
+            let (r1, r2, imm) = arg.rr();
+            cpu.cas(r1, cpu.r[r2].wrapping_add(imm));
+        });
+        table.insert("xadd", 109, RR, |cpu, arg| {
+            let (r1, r2, imm) = arg.rr();
+            cpu.fetch_add(r1, cpu.r[r2].wrapping_add(imm));
+        });
+
+        if ext & isa::SIGNED != 0 {
+            table.insert("imul", 10, RR, |cpu, arg| {
+                let (r1, r2, _) = arg.rr();
+                if !cpu.check_pair(r1) { return; }
+
+                let mul = (cpu.r[r1] as i32 as i64) * (cpu.r[r2] as i32 as i64);
+                cpu.r[r1] = mul as u32;
+                cpu.r[r1 + 1] = (mul >> 32) as u32;
+            });
+            table.insert("imuli", 11, RI, |cpu, arg| {
+                let (reg, imm) = arg.ri();
+                if !cpu.check_pair(reg) { return; }
+
+                let mul = (cpu.r[reg] as i32 as i64) * (imm as i32 as i64);
+                cpu.r[reg] = mul as u32;
+                cpu.r[reg + 1] = (mul >> 32) as u32;
+            });
+            table.insert("idiv", 25, RR, |cpu, arg| {
+                let (r1, r2, _) = arg.rr();
+                if !cpu.check_pair(r1) { return; }
+                if cpu.r[r2] == 0 { return cpu.fault(FaultKind::DivByZero); }
+
+                let (a, b) = (cpu.r[r1] as i32, cpu.r[r2] as i32);
+                cpu.r[r1] = a.wrapping_div(b) as u32;
+                cpu.r[r1 + 1] = a.wrapping_rem(b) as u32;
+            });
+            table.insert("idivi", 26, RI, |cpu, arg| {
+                let (reg, imm) = arg.ri();
+                if !cpu.check_pair(reg) { return; }
+                if imm == 0 { return cpu.fault(FaultKind::DivByZero); }
+
+                let (a, b) = (cpu.r[reg] as i32, imm as i32);
+                cpu.r[reg] = a.wrapping_div(b) as u32;
+                cpu.r[reg + 1] = a.wrapping_rem(b) as u32;
+            });
+            table.insert("sar", 27, RR, |cpu, arg| {
+                let (r1, r2, imm) = arg.rr();
+
+                let sh = cpu.r[r2].wrapping_add(imm).min(31);
+                cpu.r[r1] = ((cpu.r[r1] as i32) >> sh) as u32;
+            });
+            table.insert("sari", 28, RI, |cpu, arg| {
+                let (reg, imm) = arg.ri();
+
+                cpu.r[reg] = ((cpu.r[reg] as i32) >> imm.min(31)) as u32;
+            });
+            table.insert("cmps", 29, RR, |cpu, arg| {
+                let (r1, r2, _) = arg.rr();
+
+                cpu.cmp(cpu.r[r1] as i32, cpu.r[r2] as i32);
+            });
+            table.insert("cmpsi", 30, RI, |cpu, arg| {
+                let (reg, imm) = arg.ri();
+
+                cpu.cmp(cpu.r[reg] as i32, imm as i32);
+            });
+        }
+
+        if ext & isa::INTERRUPTS != 0 {
+            table.insert("ei", 56, RI, |cpu, _| cpu.ie = true);
+            table.insert("di", 57, RI, |cpu, _| cpu.ie = false);
+            table.insert("iret", 58, RI, |cpu, _| {
+                let f = cpu.pop();
+                let adr = cpu.pop();
+                if cpu.fault.is_some() { return; }
+
+                cpu.f = Flag::from_word(f);
+                cpu.ie = true;
+                cpu.jump(adr);
+            });
+        }
+
+        if ext & isa::FLOAT != 0 {
+            insert!(fnd => "sqrtd",  80, f64::sqrt);
+            insert!(fnd => "absd",   81, f64::abs);
+            insert!(fnd => "negd",   82, |x: f64| -x);
+            insert!(fnd => "sind",   83, f64::sin);
+            insert!(fnd => "cosd",   84, f64::cos);
+            insert!(fnd => "expd",   85, f64::exp);
+            insert!(fnd => "logd",   86, f64::ln);
+            insert!(fnd => "floord", 87, f64::floor);
+            insert!(fnd => "ceild",  88, f64::ceil);
+            insert!(fnd => "roundd", 89, f64::round);
+            // `lcd r0 -3` loads a whole number as a double, the 20-bit
+            // immediate can't hold anything else. `lcd r0 1.5` doesn't
+            // assemble: put the value in a `double` directive and `load2` it.
+            table.insert("lcd", 90, RI, |cpu, arg| {
+                let (reg, imm) = arg.ri();
+                if !cpu.check_pair(reg) { return; }
+
+                cpu.writed(imm as i32 as f64, reg);
+            });
+        }
+
+        // Bit numbers and rotation counts are taken modulo 32. `bt` compares
+        // the bit with 1, so jeq jumps if it is set.
+        if ext & isa::BITS != 0 {
+            insert!(bit  => "rol",    92, Word::rotate_left);
+            insert!(biti => "roli",   93, Word::rotate_left);
+            insert!(bit  => "ror",    94, Word::rotate_right);
+            insert!(biti => "rori",   95, Word::rotate_right);
+            insert!(un   => "popcnt", 96, Word::count_ones);
+            insert!(un   => "clz",    97, Word::leading_zeros);
+            insert!(un   => "ctz",    98, Word::trailing_zeros);
+            insert!(un   => "bswap",  99, Word::swap_bytes);
+            table.insert("bt", 100, RR, |cpu, arg| {
+                let (r1, r2, imm) = arg.rr();
+
+                cpu.cmp((cpu.r[r1] >> (cpu.r[r2].wrapping_add(imm) % 32)) & 1, 1);
+            });
+            table.insert("bti", 101, RI, |cpu, arg| {
+                let (reg, imm) = arg.ri();
+
+                cpu.cmp((cpu.r[reg] >> (imm % 32)) & 1, 1);
+            });
+            insert!(bit  => "bts",   102, |val: Word, n: Word| val | 1 << n);
+            insert!(biti => "btsi",  103, |val: Word, n: Word| val | 1 << n);
+            insert!(bit  => "btr",   104, |val: Word, n: Word| val & !(1 << n));
+            insert!(biti => "btri",  105, |val: Word, n: Word| val & !(1 << n));
+        }
+
+        table.insert("$STACK", 255, JMEM, |cpu, arg| {
+            let num = arg.jm();
 
             println!("STACK DUMP:");
             for i in 0..num {
-                print!("[{}] => {:?}", i, cpu.mem[MEMSZ - (i + 1) as usize]);
-                if MEMSZ as u32 - i - 1 == cpu.r[14] { print!("*"); }
+                let adr = match (cpu.mem.len() as u32).checked_sub(i + 1) { Some(adr) => adr, None => break };
+                print!("[{}] => {:?}", i, cpu.mem[adr as usize]);
+                if adr == cpu.r[14] { print!("*"); }
                 print!("\n");
             }
         });
+        let costs: &[(&[&str], u32)] = &[
+            (&["mul", "muli", "imul", "imuli"], 3),
+            (&["div", "divi", "idiv", "idivi"], 10),
+            (&["addd", "subd", "muld", "itod", "dtoi", "cmpd"], 4),
+            (&["absd", "negd", "floord", "ceild", "roundd"], 4),
+            (&["divd", "sqrtd"], 12),
+            (&["sind", "cosd", "expd", "logd"], 20),
+            (&["load", "store", "loadr", "storer", "push", "pop"], 2),
+            (&["loadb", "storeb", "loadh", "storeh", "loadrb", "storerb", "loadrh", "storerh"], 2),
+            (&["load2", "store2", "loadr2", "storer2", "call", "calli", "ret", "iret"], 3),
+            (&["cas", "xadd"], 3),
+            (&["syscall"], 10)
+        ];
+        for (names, cost) in costs {
+            for name in names.iter() {
+                if table.code.contains_key(*name) {
+                    table.set_cycles(name, *cost);
+                }
+            }
+        }
+
         table
     }
 }
\ No newline at end of file
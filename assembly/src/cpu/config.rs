@@ -0,0 +1,91 @@
+use super::*;
+
+/// Optional instruction groups, see `CmdTable::with_isa`.
+pub mod isa {
+    use super::Word;
+
+    pub const SIGNED:     Word = 0b0001;
+    pub const INTERRUPTS: Word = 0b0010;
+    pub const FLOAT:      Word = 0b0100;
+    pub const BITS:       Word = 0b1000;
+    pub const ALL:        Word = !0;
+}
+
+/// Groups of standard syscalls served by `IoSyscalls`.
+pub mod sysset {
+    use super::Word;
+
+    pub const BASIC:   Word = 0b000001;
+    pub const STRINGS: Word = 0b000010;
+    pub const HEAP:    Word = 0b000100;
+    pub const RANDOM:  Word = 0b001000;
+    pub const FILES:   Word = 0b010000;
+    pub const CORES:   Word = 0b100000;
+    pub const ALL:     Word = !0;
+
+    /// Group a standard syscall number belongs to, 0 for unknown ones.
+    pub fn group(num: Word) -> Word {
+        match num {
+            0..=4 | 100..=105 => BASIC,
+            106..=114 => STRINGS,
+            120..=121 => HEAP,
+            130..=133 => RANDOM,
+            140..=142 => CORES,
+            200..=204 => FILES,
+            _ => 0
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum FaultPolicy {
+    /// Faults stop the CPU and are returned from `exec`.
+    Stop,
+    /// Faults go to the guest handler at this address.
+    Trap(Word)
+}
+
+/// How `CPU::exec` runs code. Both engines give the same results,
+/// `Interp` is the reference one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Engine {
+    /// Fetches and dispatches one instruction at a time.
+    Interp,
+    /// Runs cached basic blocks of the loaded image.
+    Blocks
+}
+
+#[derive(Clone, Debug)]
+pub struct MachineConfig {
+    /// Memory size in words, including the interrupt vectors at the top.
+    pub mem_words: usize,
+    /// Initial r14, right below the interrupt vectors if `None`.
+    pub stack: Option<Word>,
+    /// `sysset` groups the standard syscall handler serves.
+    pub syscalls: Word,
+    /// `isa` groups the command table contains.
+    pub isa: Word,
+    pub faults: FaultPolicy,
+    pub engine: Engine,
+    /// How cores started with `start_core` share the CPU.
+    pub schedule: Schedule,
+    pub protection: Protection,
+    /// Record stores that overwrite loaded code, see `CpuState::code_stores`.
+    pub watch_code: bool
+}
+
+impl Default for MachineConfig {
+    fn default() -> MachineConfig {
+        MachineConfig {
+            mem_words: MEMSZ,
+            stack: None,
+            syscalls: sysset::ALL,
+            isa: isa::ALL,
+            faults: FaultPolicy::Stop,
+            engine: Engine::Interp,
+            schedule: Schedule::RoundRobin(100),
+            protection: Protection::Off,
+            watch_code: false
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use super::*;
+
+/// Registers of one core. Cores share memory, devices, files and the
+/// step counter; the running core's context lives in `CpuState` itself.
+#[derive(Clone)]
+pub struct Core {
+    pub r: [Word; 16],
+    pub f: Flag,
+    pub stack_top: Word,
+    pub ie: bool,
+    pub running: bool
+}
+
+impl Core {
+    pub fn new(pc: Word, sp: Word) -> Core {
+        let mut r = [0; 16];
+        r[14] = sp;
+        r[15] = pc;
+        Core{ r, f: Flag::NAN, stack_top: sp, ie: false, running: true }
+    }
+}
+
+/// Which core runs next once there is more than one. Both are
+/// deterministic: the same program and schedule give the same interleaving.
+#[derive(Clone, Copy, Debug)]
+pub enum Schedule {
+    /// Cores take turns in id order, `quantum` instructions each.
+    RoundRobin(u32),
+    /// A random running core is picked for every instruction.
+    Seeded(u64)
+}
+
+/// Scheduler state of a `CpuState`.
+pub struct Cores {
+    /// Contexts by core id; the entry of the running core is stale.
+    pub all: Vec<Core>,
+    pub current: usize,
+    pub schedule: Schedule,
+    rng: Rng,
+    slice_end: u64
+}
+
+impl Cores {
+    pub fn new(schedule: Schedule) -> Cores {
+        let seed = match schedule { Schedule::Seeded(seed) => seed, _ => Rng::DEFAULT_SEED };
+        Cores{ all: vec![Core::new(0, 0)], current: 0, schedule, rng: Rng::new(seed), slice_end: 0 }
+    }
+}
+
+impl CpuState {
+    /// Starts a new core at `pc` with its stack at `sp`, returns its id.
+    pub fn start_core(&mut self, pc: Word, sp: Word) -> Word {
+        self.cores.all.push(Core::new(pc, sp));
+        (self.cores.all.len() - 1) as Word
+    }
+
+    /// Stops the running core for good, the last one stops the CPU.
+    pub fn stop_core(&mut self) {
+        let cur = self.cores.current;
+        self.cores.all[cur].running = false;
+        if !self.cores.all.iter().any(|core| core.running) {
+            self.exit(0);
+        }
+    }
+
+    /// r14 of core 0, whose stack the heap grows towards.
+    pub fn main_sp(&self) -> Word {
+        if self.cores.current == 0 { self.r[14] } else { self.cores.all[0].r[14] }
+    }
+
+    fn switch_core(&mut self, id: usize) {
+        let cur = self.cores.current;
+        if id == cur { return; }
+        let saved = &mut self.cores.all[cur];
+        saved.r = self.r;
+        saved.f = self.f;
+        saved.stack_top = self.stack_top;
+        saved.ie = self.ie;
+
+        let next = &self.cores.all[id];
+        self.r = next.r;
+        self.f = next.f;
+        self.stack_top = next.stack_top;
+        self.ie = next.ie;
+        self.cores.current = id;
+    }
+
+    /// Switches cores if the running one is out of its slice or stopped.
+    /// Returns the step count the current slice ends at, at most `limit`.
+    pub fn schedule(&mut self, limit: u64) -> u64 {
+        if self.cores.all.len() == 1 {
+            return limit;
+        }
+        let cur = self.cores.current;
+        if self.steps < self.cores.slice_end && self.cores.all[cur].running {
+            return self.cores.slice_end.min(limit);
+        }
+
+        let cnt = self.cores.all.len();
+        let (next, quantum) = match self.cores.schedule {
+            Schedule::RoundRobin(quantum) => {
+                let next = (1..=cnt).map(|i| (cur + i) % cnt)
+                    .find(|&id| self.cores.all[id].running);
+                (next, quantum.max(1))
+            }
+            Schedule::Seeded(_) => {
+                let running: Vec<usize> = (0..cnt).filter(|&id| self.cores.all[id].running).collect();
+                let pick = self.cores.rng.below(running.len() as Word) as usize;
+                (running.get(pick).cloned(), 1)
+            }
+        };
+        if let Some(next) = next {
+            self.switch_core(next);
+        }
+        self.cores.slice_end = self.steps + quantum as u64;
+        self.cores.slice_end.min(limit)
+    }
+}
@@ -1,4 +1,5 @@
 use super::*;
+use std::cmp::Ordering;
 
 macro_rules! convd {
     ($fsti:expr, $sndi:expr) => { {
@@ -25,37 +26,338 @@ impl CpuState {
         self.r[reg + 1] = u2;
     }
 
+    /// Stops the CPU normally, `status` is reported to whoever ran it.
+    pub fn exit(&mut self, status: Word) {
+        self.status = status;
+        self.halt = true;
+    }
+
+    /// Stops the CPU with a fault at the current pc; the first fault wins.
+    pub fn fault(&mut self, kind: FaultKind) {
+        if self.fault.is_none() {
+            self.fault = Some(self.make_fault(kind));
+        }
+        self.halt = true;
+    }
+
+    pub fn make_fault(&self, kind: FaultKind) -> Fault {
+        let pc = self.r[15];
+        let cmd = self.mem.get(pc as usize).cloned().unwrap_or(0);
+        Fault{ kind, pc, cmd, core: self.cores.current }
+    }
+
+    /// Address of interrupt vector 0, what `syscall rN 4` returns.
+    pub fn ivt_base(&self) -> Word {
+        self.mem.len() as Word - IVT_LEN
+    }
+
+    /// Marks interrupt `line` pending, it's taken once interrupts are enabled.
+    pub fn raise(&mut self, line: Word) {
+        if line < IVT_LEN {
+            self.irq |= 1 << line;
+        }
+    }
+
+    /// Enters the handler of the lowest pending line: pushes r15 and the
+    /// flag, disables interrupts and continues at the vector. `iret` undoes it.
+    pub fn interrupt(&mut self) {
+        if !self.ie || self.irq == 0 || self.halt {
+            return;
+        }
+        let line = self.irq.trailing_zeros();
+        self.irq &= !(1 << line);
+
+        self.push(self.r[15]);
+        self.push(self.f as Word);
+        self.ie = false;
+        if self.fault.is_none() {
+            self.r[15] = self.mem[(self.ivt_base() + line) as usize];
+        }
+    }
+
+    /// Register pairs (`rN`, `rN+1`) can't start at r15.
+    pub fn check_pair(&mut self, reg: usize) -> bool {
+        self.check_regs(reg, 2)
+    }
+
+    /// Same for `cnt` consecutive registers starting at `reg`.
+    pub fn check_regs(&mut self, reg: usize, cnt: usize) -> bool {
+        if reg + cnt > self.r.len() {
+            self.fault(FaultKind::BadRegister(self.r.len()));
+            return false;
+        }
+        true
+    }
+
+    /// Only the main stack, core 0's, grows towards the heap and may not
+    /// reach the break. Other cores' stacks live wherever they were
+    /// started, e.g. in memory from `sbrk`.
     pub fn push(&mut self, val: Word) {
+        let main = self.cores.current == 0;
+        let floor = if main { self.brk } else { 0 };
+        if self.r[14] <= floor || self.r[14] as usize > self.mem.len() {
+            self.fault(FaultKind::StackOverflow);
+            return;
+        }
+        if main && self.protection != Protection::Off && self.r[14] - self.brk <= GUARD_WORDS
+            && !self.violation(FaultKind::StackOverflow) {
+            return;
+        }
         self.r[14] -= 1;
         self.mem[self.r[14] as usize] = val;
     }
 
     pub fn pop(&mut self) -> u32 {
+        if self.r[14] >= self.stack_top || self.r[14] as usize >= self.mem.len() {
+            self.fault(FaultKind::StackUnderflow);
+            return 0;
+        }
         let ret = self.mem[self.r[14] as usize];
         self.r[14] += 1;
         ret
     }
 
-    pub fn cmp<T:PartialOrd>(&mut self, val1: T, val2: T) {
-        if val1 < val2 {
-            self.f = Flag::L;
-        } else
-        if val1 > val2 {
-            self.f = Flag::G; 
-        } else {
-            self.f = Flag::E;
+    /// Moves the program break, which starts right after the program and
+    /// may grow up to core 0's stack. Returns the old break; shrinking below
+    /// the program fails with `None`, growing into the stack faults.
+    pub fn sbrk(&mut self, inc: i32) -> Option<Word> {
+        let old = self.brk;
+        let new = (old as i64) + (inc as i64);
+        if new < self.progsz as i64 {
+            return None;
+        }
+        let guard = if self.protection == Protection::Enforce { GUARD_WORDS as i64 } else { 0 };
+        if new > self.main_sp() as i64 - guard {
+            self.fault(FaultKind::HeapOverflow);
+            return None;
+        }
+
+        self.brk = new as Word;
+        Some(old)
+    }
+
+    /// Zero-terminated string stored one character per word.
+    pub fn read_str(&mut self, adr: Word) -> Option<String> {
+        let mut res = String::new();
+        let mut cur = adr;
+        loop {
+            match self.mem.get(cur as usize) {
+                Some(0) => return Some(res),
+                Some(&c) => res.push(char::from(c as u8)),
+                None => { self.fault(FaultKind::BadAddress(cur)); return None; }
+            }
+            cur = cur.wrapping_add(1);
+        }
+    }
+
+    /// Stores `text` like `read_str` expects it, cut to fit into `limit`
+    /// words with the terminator. Returns the number of characters stored.
+    pub fn write_str(&mut self, adr: Word, limit: Word, text: &str) -> Option<Word> {
+        if limit == 0 { return Some(0); }
+        let chars: Vec<Word> = text.bytes().take(limit as usize - 1).map(Word::from).collect();
+        let end = adr as usize + chars.len();
+        if end >= self.mem.len() {
+            self.fault(FaultKind::BadAddress(adr.max(self.mem.len() as Word)));
+            return None;
+        }
+        if !self.access(adr, end as Word + 1, perm::W) { return None; }
+
+        self.mem[adr as usize..end].copy_from_slice(&chars);
+        self.mem[end] = 0;
+        self.wrote(adr, end as Word + 1);
+        Some(chars.len() as Word)
+    }
+
+    /// Zero-terminated string packed four characters per word, `adr` is a
+    /// byte address like for `load_part`.
+    pub fn read_pstr(&mut self, adr: Word) -> Option<String> {
+        let mut res = String::new();
+        let mut cur = adr;
+        loop {
+            match self.mem.get(cur as usize / 4) {
+                Some(&word) => match (word >> (cur % 4 * 8)) as u8 {
+                    0 => return Some(res),
+                    c => res.push(char::from(c))
+                },
+                None => { self.fault(FaultKind::BadAddress(cur / 4)); return None; }
+            }
+            cur = cur.wrapping_add(1);
         }
     }
 
+    /// Stores `text` like `read_pstr` expects it, cut to fit into `limit`
+    /// bytes with the terminator. Returns the number of characters stored.
+    pub fn write_pstr(&mut self, adr: Word, limit: Word, text: &str) -> Option<Word> {
+        if limit == 0 { return Some(0); }
+        let mut bytes: Vec<u8> = text.bytes().take(limit as usize - 1).collect();
+        let len = bytes.len() as Word;
+        bytes.push(0);
+        let end = adr as usize + bytes.len();
+        if end > self.mem.len() * 4 {
+            self.fault(FaultKind::BadAddress((adr / 4).max(self.mem.len() as Word)));
+            return None;
+        }
+        if !self.access(adr / 4, end.div_ceil(4) as Word, perm::W) { return None; }
+
+        for (i, byte) in bytes.into_iter().enumerate() {
+            let cur = adr as usize + i;
+            let shift = cur % 4 * 8;
+            let cell = &mut self.mem[cur / 4];
+            *cell = (*cell & !(0xff << shift)) | (Word::from(byte) << shift);
+        }
+        self.wrote(adr / 4, end.div_ceil(4) as Word);
+        Some(len)
+    }
+
+    /// Puts program arguments and environment below the current r14:
+    ///
+    /// ```text
+    /// r14 before -> strings, zero-terminated, one character per word
+    ///               envp[0], envp[1], ..., 0     <- r2
+    /// r14 after  -> argv[0], argv[1], ..., 0     <- r1
+    /// ```
+    ///
+    /// `r0` gets argc and the stack starts at the new r14, so popping more
+    /// than was pushed still faults. Running out of stack faults like
+    /// `push` does.
+    pub fn set_args<S: AsRef<str>>(&mut self, args: &[S], env: &[S]) {
+        let push_strs = |cpu: &mut CpuState, strs: &[S]| -> Vec<Word> {
+            strs.iter().map(|s| {
+                cpu.push(0);
+                for c in s.as_ref().bytes().rev() {
+                    cpu.push(Word::from(c));
+                }
+                cpu.r[14]
+            }).collect()
+        };
+        let env_adrs = push_strs(self, env);
+        let arg_adrs = push_strs(self, args);
+
+        let push_table = |cpu: &mut CpuState, adrs: &[Word]| -> Word {
+            cpu.push(0);
+            for adr in adrs.iter().rev() {
+                cpu.push(*adr);
+            }
+            cpu.r[14]
+        };
+        self.r[2] = push_table(self, &env_adrs);
+        self.r[1] = push_table(self, &arg_adrs);
+        self.r[0] = args.len() as Word;
+        self.stack_top = self.r[14];
+    }
+
+    pub fn cmp<T:PartialOrd>(&mut self, val1: T, val2: T) {
+        self.f = match val1.partial_cmp(&val2) {
+            Some(Ordering::Less)    => Flag::L,
+            Some(Ordering::Greater) => Flag::G,
+            Some(Ordering::Equal)   => Flag::E,
+            None                    => Flag::U
+        };
+    }
+
     pub fn jump(&mut self, adr: u32) {
         self.r[15] = adr.wrapping_sub(1);
     }
 
     pub fn load(&mut self, adr: u32, reg: usize) {
-        self.r[reg] = self.mem[adr as usize];
+        if let Some(val) = self.read(adr) {
+            self.r[reg] = val;
+        }
     }
 
     pub fn store(&mut self, reg: usize, adr: u32) {
-        self.mem[adr as usize] = self.r[reg];
+        self.write(adr, self.r[reg]);
+    }
+
+    fn read(&mut self, adr: u32) -> Option<Word> {
+        if let Some((dev, off)) = self.bus.device(adr) {
+            return Some(dev.read(off));
+        }
+        match self.mem.get(adr as usize) {
+            Some(&val) => if self.access(adr, adr + 1, perm::R) { Some(val) } else { None },
+            None       => { self.fault(FaultKind::BadAddress(adr)); None }
+        }
+    }
+
+    fn write(&mut self, adr: u32, val: Word) {
+        if let Some((dev, off)) = self.bus.device(adr) {
+            dev.write(off, val);
+            return;
+        }
+        if adr as usize >= self.mem.len() {
+            return self.fault(FaultKind::BadAddress(adr));
+        }
+        if !self.access(adr, adr + 1, perm::W) { return; }
+        if self.watch_code && adr < self.cnst_start && self.code_stores.len() < MAX_CODE_STORES {
+            let old = self.mem[adr as usize];
+            self.code_stores.push(CodeStore{ pc: self.r[15], core: self.cores.current, adr, old, new: val });
+        }
+        self.mem[adr as usize] = val;
+        self.wrote(adr, adr + 1);
+    }
+
+    /// Compare-and-swap at `adr`: if the word there equals `rN` it is
+    /// replaced with `rN+1`. `rN` gets the old word and the flag is set like
+    /// `cmp` of it with `rN`, so `jeq` means the swap happened.
+    pub fn cas(&mut self, reg: usize, adr: Word) {
+        if !self.check_pair(reg) { return; }
+        let old = match self.read(adr) { Some(old) => old, None => return };
+        if old == self.r[reg] {
+            self.write(adr, self.r[reg + 1]);
+        }
+        self.cmp(old, self.r[reg]);
+        self.r[reg] = old;
+    }
+
+    /// Fetch-and-add at `adr`: adds `rN` to the word there, `rN` gets the old word.
+    pub fn fetch_add(&mut self, reg: usize, adr: Word) {
+        if let Some(old) = self.read(adr) {
+            self.write(adr, old.wrapping_add(self.r[reg]));
+            self.r[reg] = old;
+        }
+    }
+
+    /// Loads `size` bytes (1 or 2) from byte address `adr`, zero-extended.
+    /// Bytes are packed little-endian: byte `4 * w + i` is bits
+    /// `8 * i .. 8 * i + 8` of word `w`. Halfword addresses must be even.
+    /// `loadb` and `loadh` encode `adr` in 20 bits, so they only reach
+    /// bytes below 2^20; `loadrb` and `loadrh` reach all of memory.
+    pub fn load_part(&mut self, adr: Word, size: Word, reg: usize) {
+        if !adr.is_multiple_of(size) { return self.fault(FaultKind::BadAddress(adr)); }
+        let (shift, mask) = (adr % 4 * 8, Word::MAX >> (32 - size * 8));
+        if let Some(word) = self.read(adr / 4) {
+            self.r[reg] = (word >> shift) & mask;
+        }
+    }
+
+    /// Stores the low `size` bytes of `reg` at byte address `adr`, see
+    /// `load_part`. Devices get just the stored part as the whole word.
+    pub fn store_part(&mut self, reg: usize, adr: Word, size: Word) {
+        if !adr.is_multiple_of(size) { return self.fault(FaultKind::BadAddress(adr)); }
+        let (shift, mask) = (adr % 4 * 8, Word::MAX >> (32 - size * 8));
+        let val = self.r[reg] & mask;
+        if let Some((dev, off)) = self.bus.device(adr / 4) {
+            return dev.write(off, val);
+        }
+        if let Some(word) = self.read(adr / 4) {
+            self.write(adr / 4, (word & !(mask << shift)) | (val << shift));
+        }
+    }
+
+    /// Notes that `[start, end)` was written. Code that writes into the
+    /// loaded image straight through `mem` must call it too, so cached
+    /// blocks of the old code are dropped.
+    pub fn wrote(&mut self, mut start: Word, mut end: Word) {
+        if start >= self.progsz {
+            return;
+        }
+        // Nothing drains the list while the interpreter runs, so a program
+        // patching itself in a loop collapses it into the whole image.
+        if self.code_writes.len() >= 64 {
+            self.code_writes.clear();
+            start = 0;
+            end = self.progsz;
+        }
+        self.code_writes.push((start, end));
     }
 }
\ No newline at end of file
@@ -0,0 +1,36 @@
+use super::*;
+
+/// Decoded commands of the loaded image `[0, progsz)`, filled as they are
+/// executed. Every entry keeps the word it was decoded from and is only
+/// used while memory still holds that word, so writes into code by
+/// `store`, syscalls, patches or the host invalidate it. Redefining
+/// commands in the table drops the whole cache.
+#[derive(Default)]
+pub(crate) struct Decoded {
+    instrs: Vec<Option<Instr>>,
+    generation: u64
+}
+
+impl Decoded {
+    /// Decoded `word` found at `pc`, `None` for an unknown opcode.
+    pub fn fetch(&mut self, table: &CmdTable, progsz: u32, pc: Word, word: Word) -> Option<Instr> {
+        if pc >= progsz {
+            return table.get(getcode!(word)).map(|cmd| Instr::decode(word, cmd.format));
+        }
+        if self.generation != table.generation() || self.instrs.len() != progsz as usize {
+            self.instrs.clear();
+            self.instrs.resize(progsz as usize, None);
+            self.generation = table.generation();
+        }
+
+        let slot = &mut self.instrs[pc as usize];
+        match slot {
+            Some(instr) if instr.word == word => Some(*instr),
+            _ => {
+                let instr = Instr::decode(word, table.get(getcode!(word))?.format);
+                *slot = Some(instr);
+                Some(instr)
+            }
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use super::*;
+use std::io::{self, BufRead, Stdout, Write};
+
+/// Character console. Offset 0 reads the next input byte (`FILE_ERR` at
+/// the end of input) and writes a byte to the output.
+pub struct Console<R, W> {
+    pub input: R,
+    pub output: W
+}
+
+impl Console<SharedStdin, Stdout> {
+    /// Reads the same input as `IoSyscalls::stdio`.
+    pub fn stdio() -> Self {
+        Console{ input: SharedStdin::new(), output: io::stdout() }
+    }
+}
+
+impl<R: BufRead, W: Write> Device for Console<R, W> {
+    fn size(&self) -> Word { 1 }
+
+    fn read(&mut self, _: Word) -> Word {
+        self.output.flush().ok();
+        let byte = match self.input.fill_buf() {
+            Ok(buf) if !buf.is_empty() => buf[0],
+            _ => return FILE_ERR
+        };
+        self.input.consume(1);
+        byte as Word
+    }
+
+    fn write(&mut self, _: Word, val: Word) {
+        self.output.write_all(&[val as u8]).expect("Unable to write output!");
+    }
+}
+
+/// Counter of executed instructions: offset 0 is the low word, offset 1
+/// the high one, writing either resets it. Offset 2 is the period: with a
+/// non-zero period the timer raises the line at offset 3 every `period`
+/// instructions.
+#[derive(Default)]
+pub struct Timer {
+    pub count: u64,
+    pub period: Word,
+    pub line: Word
+}
+
+impl Device for Timer {
+    fn size(&self) -> Word { 4 }
+
+    fn read(&mut self, off: Word) -> Word {
+        match off {
+            0 => self.count as Word,
+            1 => (self.count >> 32) as Word,
+            2 => self.period,
+            _ => self.line
+        }
+    }
+
+    fn write(&mut self, off: Word, val: Word) {
+        match off {
+            0 | 1 => self.count = 0,
+            2 => self.period = val,
+            _ => self.line = val
+        }
+    }
+
+    fn tick(&mut self) -> Option<Word> {
+        self.count += 1;
+        if self.period != 0 && self.count.is_multiple_of(self.period as u64) {
+            return Some(self.line);
+        }
+        None
+    }
+}
+
+/// Text-mode screen of `width * height` character cells, row by row.
+/// Writing the word right after the cells prints the whole screen.
+/// There are at most `MEMSZ` cells, so it fits the reachable addresses.
+pub struct Screen<W> {
+    pub width: Word,
+    pub height: Word,
+    pub cells: Vec<Word>,
+    pub output: W
+}
+
+impl Screen<Stdout> {
+    pub fn stdout(width: Word, height: Word) -> Option<Self> {
+        Screen::new(width, height, io::stdout())
+    }
+}
+
+impl<W: Write> Screen<W> {
+    /// `None` if the screen has more than `MEMSZ` cells.
+    pub fn new(width: Word, height: Word, output: W) -> Option<Self> {
+        let cnt = width.checked_mul(height).filter(|cnt| *cnt as usize <= MEMSZ)?;
+        Some(Screen{ width, height, cells: vec![b' ' as Word; cnt as usize], output })
+    }
+
+    pub fn render(&self) -> String {
+        let mut res = String::new();
+        for row in self.cells.chunks(self.width.max(1) as usize) {
+            res.extend(row.iter().map(|c| char::from(*c as u8)));
+            res.push('\n');
+        }
+        res
+    }
+}
+
+impl<W: Write> Device for Screen<W> {
+    fn size(&self) -> Word { self.cells.len() as Word + 1 }
+
+    fn read(&mut self, off: Word) -> Word {
+        self.cells.get(off as usize).cloned().unwrap_or(0)
+    }
+
+    fn write(&mut self, off: Word, val: Word) {
+        match self.cells.get_mut(off as usize) {
+            Some(cell) => *cell = val,
+            None => {
+                let screen = self.render();
+                self.output.write_all(screen.as_bytes()).expect("Unable to write output!");
+            }
+        }
+    }
+}
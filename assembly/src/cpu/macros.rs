@@ -22,4 +22,22 @@ macro_rules! prs {
         let (_, mem) : (_, u32) = prs!(RM => $word);
         mem
         } };
-}
\ No newline at end of file
+}
+
+macro_rules! get_bytes {
+    ($word:expr) => { [
+        ($word & 255) as u8,
+        (($word >> 8)  & 255) as u8,
+        (($word >> 16) & 255) as u8,
+        (($word >> 24) & 255) as u8
+        ] }
+}
+
+macro_rules! get_word {
+    ($bytes:expr) => {
+        ($bytes[0] as u32)         +
+        (($bytes[1] as u32) << 8)  +
+        (($bytes[2] as u32) << 16) +
+        (($bytes[3] as u32) << 24)
+    }
+}
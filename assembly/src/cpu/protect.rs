@@ -0,0 +1,86 @@
+use super::*;
+
+/// Access rights `CpuState::perms` reports.
+pub mod perm {
+    use super::Word;
+
+    pub const R: Word = 0b001;
+    pub const W: Word = 0b010;
+    pub const X: Word = 0b100;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Protection {
+    /// Any word may be read, written and executed.
+    Off,
+    /// Violations fault like any other error.
+    Enforce,
+    /// Violations are recorded in `CpuState::violations` and allowed.
+    Log
+}
+
+/// Words above the program break nothing may touch, so the heap and the
+/// stack can't grow into each other unnoticed.
+pub const GUARD_WORDS: Word = 256;
+/// `CpuState::violations` keeps only the first ones.
+pub const MAX_VIOLATIONS: usize = 1000;
+
+impl CpuState {
+    /// Rights to the word at `adr` while protection is on:
+    ///
+    /// ```text
+    /// [0, cnst_start)              code        R X
+    /// [cnst_start, data_start)     constants   R
+    /// [data_start, brk)            data, heap  R W
+    /// [brk, brk + GUARD_WORDS)     guard
+    /// the rest                     stack, free R W
+    /// ```
+    pub fn perms(&self, adr: Word) -> Word {
+        if adr < self.cnst_start {
+            perm::R | perm::X
+        } else if adr < self.data_start {
+            perm::R
+        } else if adr < self.progsz.max(self.brk) {
+            perm::R | perm::W
+        } else if adr - self.brk < GUARD_WORDS {
+            0
+        } else {
+            perm::R | perm::W
+        }
+    }
+
+    /// Checks that `[start, end)` allows `need`. Returns whether the access
+    /// may go ahead: a violation faults unless protection only logs.
+    pub fn access(&mut self, start: Word, end: Word, need: Word) -> bool {
+        if self.protection == Protection::Off {
+            return true;
+        }
+        match (start..end).find(|&adr| self.perms(adr) & need != need) {
+            Some(adr) => self.violation(FaultKind::Protection(adr)),
+            None => true
+        }
+    }
+
+    /// Handles a protection violation, see `access`.
+    pub fn violation(&mut self, kind: FaultKind) -> bool {
+        match self.protection {
+            Protection::Off => true,
+            Protection::Enforce => { self.fault(kind); false }
+            Protection::Log => {
+                if self.violations.len() < MAX_VIOLATIONS {
+                    let fault = self.make_fault(kind);
+                    self.violations.push(fault);
+                }
+                true
+            }
+        }
+    }
+
+    /// End of the part of the image commands may run from.
+    pub fn exec_end(&self) -> Word {
+        match self.protection {
+            Protection::Off => self.progsz,
+            _ => self.cnst_start
+        }
+    }
+}
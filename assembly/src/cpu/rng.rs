@@ -0,0 +1,38 @@
+/// xorshift64* generator: cheap, seedable and identical on every host,
+/// so guest programs replay the same numbers for the same seed.
+#[derive(Clone)]
+pub struct Rng {
+    state: u64
+}
+
+impl Rng {
+    pub const DEFAULT_SEED: u64 = 0x0046_5550_4d32;
+
+    pub fn new(seed: u64) -> Rng {
+        let mut rng = Rng{ state: 0 };
+        rng.seed(seed);
+        rng
+    }
+
+    pub fn seed(&mut self, seed: u64) {
+        self.state = match seed ^ 0x9e37_79b9_7f4a_7c15 {
+            0 => 0x9e37_79b9_7f4a_7c15,
+            state => state
+        };
+    }
+
+    pub fn next_word(&mut self) -> u32 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        (self.state.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 32) as u32
+    }
+
+    /// Uniform-ish number in `[0, bound)`, the whole range for 0.
+    pub fn below(&mut self, bound: u32) -> u32 {
+        match bound {
+            0 => self.next_word(),
+            _ => ((self.next_word() as u64 * bound as u64) >> 32) as u32
+        }
+    }
+}
@@ -2,69 +2,141 @@ use std::fs::File;
 use std::io::{Write, Read, SeekFrom, Seek};
 use super::*;
 
+pub const EXEC_MAGIC: &[u8; 16] = b"ThisIsFUPM2Exec\0";
+pub const EXEC_HDRSZ: u64 = 512;
+
+/// Fixed part of a `ThisIsFUPM2Exec` file, stored right after the magic.
+/// Sections follow at `EXEC_HDRSZ` in order: code, constants, data.
+pub struct ExecHeader {
+	pub prog_size: u32,
+	pub cnst_size: u32,
+	pub data_size: u32,
+	pub begn_addr: u32,
+	pub stck_addr: u32,
+	/// Memory size the program asks for in words, 0 for the runner's default.
+	pub mem_words: u32
+}
+
+impl ExecHeader {
+	pub const FIELDS: usize = 6;
+
+	/// Parses the magic and header fields, `None` if `bytes` isn't an exec file.
+	pub fn parse(bytes: &[u8]) -> Option<ExecHeader> {
+		let end = EXEC_MAGIC.len() + ExecHeader::FIELDS * 4;
+		if bytes.len() < end || &bytes[..EXEC_MAGIC.len()] != EXEC_MAGIC {
+			return None;
+		}
+
+		let pars = &bytes[EXEC_MAGIC.len()..end];
+		Some(ExecHeader {
+			prog_size: get_word!(pars[0..4]),
+			cnst_size: get_word!(pars[4..8]),
+			data_size: get_word!(pars[8..12]),
+			begn_addr: get_word!(pars[12..16]),
+			stck_addr: get_word!(pars[16..20]),
+			mem_words: get_word!(pars[20..24])
+		})
+	}
+
+	/// Words of all sections together, they are loaded back to back from 0.
+	pub fn image_size(&self) -> u32 {
+		self.prog_size.saturating_add(self.cnst_size).saturating_add(self.data_size)
+	}
+
+	/// Byte range of every section in the file, as `(name, start, end)`.
+	pub fn sections(&self) -> [(&'static str, u64, u64); 3] {
+		let code = EXEC_HDRSZ + self.prog_size as u64 * 4;
+		let cnst = code + self.cnst_size as u64 * 4;
+		let data = cnst + self.data_size as u64 * 4;
+
+		[("code", EXEC_HDRSZ, code), ("const", code, cnst), ("data", cnst, data)]
+	}
+
+	pub fn write(&self, f: &mut File) {
+		f.write_all(EXEC_MAGIC).expect("Unable to write name to file!");
+
+		f.write_all(&get_bytes!(self.prog_size)).unwrap();
+		f.write_all(&get_bytes!(self.cnst_size)).unwrap();
+		f.write_all(&get_bytes!(self.data_size)).unwrap();
+		f.write_all(&get_bytes!(self.begn_addr)).unwrap();
+		f.write_all(&get_bytes!(self.stck_addr)).unwrap();
+		f.write_all(&get_bytes!(self.mem_words)).unwrap();
+	}
+}
+
 impl CPU {
+	pub fn header(&self) -> ExecHeader {
+		ExecHeader {
+			prog_size: self.state.cnst_start,
+			cnst_size: self.state.data_start - self.state.cnst_start,
+			data_size: self.state.progsz - self.state.data_start,
+			begn_addr: self.state.r[15],
+			stck_addr: self.state.r[14],
+			mem_words: if self.state.mem.len() == MEMSZ { 0 } else { self.state.mem.len() as u32 }
+		}
+	}
+
 	pub fn save(&self, f: &mut File) {
-		let name = "ThisIsFUPM2Exec\0".as_bytes();
-		f.write_all(name).expect("Unable to write name to file!");
-
-		macro_rules! get_bytes {
-			($word:expr) => { [ 
-			(($word >> 0)  as u8) & 255u8,
-			(($word >> 8)  as u8) & 255u8,
-			(($word >> 16) as u8) & 255u8,
-			(($word >> 24) as u8) & 255u8 ]
-			}
+		let header = self.header();
+		header.write(f);
+
+		f.seek(SeekFrom::Start(EXEC_HDRSZ)).unwrap();
+		for cmd in &self.state.mem[..self.state.progsz as usize] {
+			f.write_all(&get_bytes!(cmd)).unwrap();
 		}
-		let prog_size : u32 = self.state.progsz;
-		let cnst_size : u32 = 0;
-		let data_size : u32 = 0;
-		let begn_addr : u32 = self.state.r[15];
-		let stck_addr : u32 = self.state.r[14];
-
-		f.write_all(&get_bytes!(prog_size)).unwrap();
-		f.write_all(&get_bytes!(cnst_size)).unwrap();
-		f.write_all(&get_bytes!(data_size)).unwrap();
-		f.write_all(&get_bytes!(begn_addr)).unwrap();
-		f.write_all(&get_bytes!(stck_addr)).unwrap();
-
-		f.seek(SeekFrom::Start(512)).unwrap();
-		let prog_code = &self.state.mem[..prog_size as usize];
-		for cmd in prog_code {
+	}
+
+	/// Writes the entry point and the image back into the exec `f` was
+	/// loaded from, leaving the rest of the header intact.
+	pub fn update(&self, f: &mut File) {
+		let mut head = [0u8; EXEC_MAGIC.len() + ExecHeader::FIELDS * 4];
+		f.seek(SeekFrom::Start(0)).unwrap();
+		f.read_exact(&mut head).expect("Unable to read exec header!");
+		let header = ExecHeader::parse(&head).expect("Not a FUPM2 executable!");
+		assert!(header.image_size() == self.state.progsz, "Image size differs from the exec being updated!");
+
+		f.seek(SeekFrom::Start(EXEC_MAGIC.len() as u64 + 3 * 4)).unwrap();
+		f.write_all(&get_bytes!(self.state.r[15])).unwrap();
+
+		f.seek(SeekFrom::Start(EXEC_HDRSZ)).unwrap();
+		for cmd in &self.state.mem[..self.state.progsz as usize] {
 			f.write_all(&get_bytes!(cmd)).unwrap();
 		}
 	}
 
 	pub fn load(&mut self, f: &mut File) {
-		let mut name : [u8; 16] = [0; 16];
-		f.read(&mut name).expect("Unable to read compiler name!");
-		assert!(name == "ThisIsFUPM2Exec\0".as_bytes());
-
-		macro_rules! get_word {
-			($bytes:expr) => {
-				(($bytes[0] as u32) << 0)  + 
-				(($bytes[1] as u32) << 8)  + 
-				(($bytes[2] as u32) << 16) + 
-				(($bytes[3] as u32) << 24)
-			}
+		let mut head = [0u8; EXEC_MAGIC.len() + ExecHeader::FIELDS * 4];
+		f.read_exact(&mut head).expect("Unable to read exec header!");
+		let header = ExecHeader::parse(&head).expect("Not a FUPM2 executable!");
+
+		f.seek(SeekFrom::Start(EXEC_HDRSZ)).expect("Unable to seek to code!");
+		assert!(header.mem_words as usize <= MAX_MEM_WORDS,
+			"Exec asks for {} words of memory, at most {} are allowed!", header.mem_words, MAX_MEM_WORDS);
+		if header.mem_words != 0 {
+			self.state.resize(header.mem_words as usize);
 		}
-		let mut pars : [u8; 5 * 4] = [0; 5 * 4];
-		f.read(&mut pars).expect("Unable to read pars!");
-
-		let prog_size : u32 = get_word!(pars[0..4]);
-		let cnst_size : u32 = get_word!(pars[4..8]);
-		let data_size : u32 = get_word!(pars[8..12]);
-		let begn_addr : u32 = get_word!(pars[12..16]);
-		let stck_addr : u32 = get_word!(pars[16..20]);
-
-		f.seek(SeekFrom::Start(512));
-		self.state.r[14] = stck_addr;
-		self.state.r[15] = begn_addr;
-		self.state.progsz = prog_size;
-		for i in 0..prog_size - 1 {
+		let size = header.image_size();
+		assert!(size as usize <= self.state.mem.len(), "Program doesn't fit into memory!");
+		// Programs that don't ask for a memory size get the default stack
+		// at the top of whatever memory the runner has.
+		let default_stack = header.stck_addr as usize >= MEMSZ - IVT_LEN as usize;
+		self.state.r[14] = if header.mem_words == 0 && default_stack {
+			self.state.ivt_base()
+		} else {
+			header.stck_addr
+		};
+		self.state.stack_top = self.state.r[14];
+		self.state.r[15] = header.begn_addr;
+		self.state.cnst_start = header.prog_size;
+		self.state.data_start = header.prog_size + header.cnst_size;
+		self.state.progsz = size;
+		self.state.brk = size;
+		for i in 0..size {
 			let mut byte_arr : [u8; 4] = [0; 4];
-			f.read(&mut byte_arr).expect("Unable to read line!");
+			f.read_exact(&mut byte_arr).expect("Unable to read line!");
 
 			self.state.mem[i as usize] = get_word!(byte_arr);
 		}
+		self.state.wrote(0, size);
 	}
-}
\ No newline at end of file
+}
@@ -0,0 +1,250 @@
+use super::*;
+use std::cell::RefCell;
+use std::io::{self, BufRead, BufReader, Read, Stdin, Stdout, Write};
+use std::mem;
+use std::str::FromStr;
+
+/// Everything `syscall rN num` does is delegated to the CPU's handler.
+/// Custom handlers can serve their own numbers and fall back to
+/// `IoSyscalls` for the standard ones.
+pub trait SyscallHandler {
+    /// Runs syscall `num` with `reg` as its register argument.
+    /// Returns `false` if `num` isn't known, which faults the CPU.
+    fn syscall(&mut self, cpu: &mut CpuState, reg: usize, num: Word) -> bool;
+}
+
+type StrWriter = fn(&mut CpuState, Word, Word, &str) -> Option<Word>;
+
+/// Standard syscalls reading from `input` and printing to `output`.
+pub struct IoSyscalls<R, W> {
+    pub input: R,
+    pub output: W
+}
+
+impl IoSyscalls<SharedStdin, Stdout> {
+    pub fn stdio() -> Self {
+        IoSyscalls::new(SharedStdin::new(), io::stdout())
+    }
+}
+
+/// Output buffer that can be read back after a clone of it is boxed into
+/// `CpuState::syscalls` or a device, e.g. as the output of `IoSyscalls::new`.
+#[derive(Clone, Default)]
+pub struct SharedBuf(pub Rc<RefCell<Vec<u8>>>);
+
+impl SharedBuf {
+    pub fn new() -> Self {
+        SharedBuf::default()
+    }
+
+    /// Everything written so far.
+    pub fn bytes(&self) -> Vec<u8> {
+        self.0.borrow().clone()
+    }
+
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.0.borrow()).into_owned()
+    }
+}
+
+impl Write for SharedBuf {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> { Ok(()) }
+}
+
+/// Most bytes `SharedStdin::fill_buf` copies at once.
+const SHARED_CHUNK: usize = 256;
+
+thread_local! {
+    static STDIN: Rc<RefCell<BufReader<Stdin>>> = Rc::new(RefCell::new(BufReader::new(io::stdin())));
+}
+
+/// Standard input behind one buffer shared by every `SharedStdin` of the
+/// thread, so the syscalls and the console device don't read ahead of
+/// each other.
+pub struct SharedStdin {
+    inner: Rc<RefCell<BufReader<Stdin>>>,
+    buf: Vec<u8>
+}
+
+impl SharedStdin {
+    pub fn new() -> Self {
+        SharedStdin{ inner: STDIN.with(Rc::clone), buf: Vec::new() }
+    }
+}
+
+impl Default for SharedStdin {
+    fn default() -> Self { SharedStdin::new() }
+}
+
+impl Read for SharedStdin {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.borrow_mut().read(buf)
+    }
+}
+
+impl BufRead for SharedStdin {
+    /// A copy of the start of the shared buffer: nothing leaves it until
+    /// `consume`.
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        let mut inner = self.inner.borrow_mut();
+        let buf = inner.fill_buf()?;
+        self.buf.clear();
+        self.buf.extend_from_slice(&buf[..buf.len().min(SHARED_CHUNK)]);
+        Ok(&self.buf)
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.inner.borrow_mut().consume(amt);
+    }
+}
+
+impl<R: BufRead, W: Write> IoSyscalls<R, W> {
+    pub fn new(input: R, output: W) -> Self {
+        IoSyscalls{ input, output }
+    }
+
+    /// Next input line without the line break, `None` at the end of input.
+    fn readline(&mut self) -> Option<String> {
+        self.output.flush().ok();
+        let mut in_txt = String::new();
+        if self.input.read_line(&mut in_txt).ok()? == 0 {
+            return None;
+        }
+        let len = in_txt.trim_end_matches(&['\n', '\r'][..]).len();
+        in_txt.truncate(len);
+        Some(in_txt)
+    }
+
+    /// Next whitespace-separated token, `None` at the end of input.
+    fn readtoken(&mut self) -> Option<String> {
+        self.output.flush().ok();
+        let mut tok = Vec::new();
+        loop {
+            let buf = self.input.fill_buf().ok()?;
+            if buf.is_empty() { break; }
+
+            let mut used = 0;
+            let mut done = false;
+            for &byte in buf {
+                if !byte.is_ascii_whitespace() {
+                    tok.push(byte);
+                } else if !tok.is_empty() {
+                    done = true;
+                    break;
+                }
+                used += 1;
+            }
+            self.input.consume(used);
+            if done { break; }
+        }
+
+        if tok.is_empty() { None } else { Some(String::from_utf8_lossy(&tok).into_owned()) }
+    }
+
+    fn parseline<T: FromStr>(&mut self) -> Option<T> {
+        self.readline()?.trim().parse().ok()
+    }
+
+    /// `rN` = buffer, `rN+1` = limit; `rN` gets the length or `FILE_ERR` at
+    /// the end of input. `write` is `CpuState::write_str` or `write_pstr`.
+    fn readstr(&mut self, cpu: &mut CpuState, reg: usize, text: Option<String>, write: StrWriter) {
+        if !cpu.check_pair(reg) { return; }
+        match text {
+            Some(text) => if let Some(len) = write(cpu, cpu.r[reg], cpu.r[reg + 1], &text) {
+                cpu.r[reg] = len;
+            },
+            None => cpu.r[reg] = FILE_ERR
+        }
+    }
+}
+
+impl<R: BufRead, W: Write> SyscallHandler for IoSyscalls<R, W> {
+    fn syscall(&mut self, cpu: &mut CpuState, reg: usize, num: Word) -> bool {
+        macro_rules! out {
+            ($($arg:tt)*) => { write!(self.output, $($arg)*).expect("Unable to write output!") };
+        }
+        macro_rules! input {
+            ($T:ty => $val:ident, $act:expr) => {
+                match self.parseline::<$T>() {
+                    Some($val) => $act,
+                    None => cpu.fault(FaultKind::BadInput)
+                }
+            };
+        }
+
+        if cpu.sysset & sysset::group(num) == 0 {
+            return false;
+        }
+
+        match num {
+            0 => cpu.exit(0),
+            1 => cpu.exit(cpu.r[reg]),
+            2 => cpu.fault_handler = Some(cpu.r[reg]),
+            3 => cpu.fault_handler = None,
+            4 => cpu.r[reg] = cpu.ivt_base(),
+            100 => input!(u32 => val, cpu.r[reg] = val),
+            101 => if cpu.check_pair(reg) { input!(f64 => val, cpu.writed(val, reg)) },
+            102 => out!("{}", cpu.r[reg]),
+            103 => if cpu.check_pair(reg) { out!("{}", cpu.scand(reg)) },
+            104 => input!(char => val, cpu.r[reg] = val as u32),
+            105 => out!("{}", (cpu.r[reg] as u8) as char),
+            106 => if let Some(text) = cpu.read_str(cpu.r[reg]) { out!("{}", text) },
+            107 => out!("{}", cpu.r[reg] as i32),
+            108 => out!("{:x}", cpu.r[reg]),
+            109 => { let line = self.readline(); self.readstr(cpu, reg, line, CpuState::write_str) },
+            110 => { let tok = self.readtoken(); self.readstr(cpu, reg, tok, CpuState::write_str) },
+            111 => match self.readtoken().and_then(|tok| tok.parse::<i32>().ok()) {
+                Some(val) => cpu.r[reg] = val as Word,
+                None => cpu.fault(FaultKind::BadInput)
+            },
+            112 => if let Some(text) = cpu.read_pstr(cpu.r[reg]) { out!("{}", text) },
+            113 => { let line = self.readline(); self.readstr(cpu, reg, line, CpuState::write_pstr) },
+            114 => { let tok = self.readtoken(); self.readstr(cpu, reg, tok, CpuState::write_pstr) },
+            120 => if let Some(old) = cpu.sbrk(cpu.r[reg] as i32) { cpu.r[reg] = old }
+                   else if cpu.fault.is_none() { cpu.r[reg] = FILE_ERR },
+            121 => {
+                let inc = cpu.r[reg].wrapping_sub(cpu.brk) as i32;
+                cpu.r[reg] = if cpu.sbrk(inc).is_some() { 0 } else { FILE_ERR };
+            }
+            130 => cpu.r[reg] = cpu.rng.next_word(),
+            131 => cpu.rng.seed(cpu.r[reg] as u64),
+            132 => if cpu.check_pair(reg) {
+                cpu.r[reg] = cpu.steps as Word;
+                cpu.r[reg + 1] = (cpu.steps >> 32) as Word;
+            },
+            133 => cpu.r[reg] = cpu.rng.below(cpu.r[reg]),
+            140 => cpu.r[reg] = cpu.cores.current as Word,
+            141 => if cpu.check_pair(reg) { cpu.r[reg] = cpu.start_core(cpu.r[reg], cpu.r[reg + 1]) },
+            142 => cpu.stop_core(),
+            200 => cpu.file_open(reg),
+            201 => cpu.file_read(reg),
+            202 => cpu.file_write(reg),
+            203 => cpu.file_close(reg),
+            204 => cpu.file_seek(reg),
+
+            _ => return false
+        }
+        true
+    }
+}
+
+struct NoSyscalls;
+
+impl SyscallHandler for NoSyscalls {
+    fn syscall(&mut self, _: &mut CpuState, _: usize, _: Word) -> bool { false }
+}
+
+impl CpuState {
+    pub fn syscall(&mut self, reg: usize, num: Word) {
+        let mut handler = mem::replace(&mut self.syscalls, Box::new(NoSyscalls));
+        if !handler.syscall(self, reg, num) {
+            self.fault(FaultKind::BadSyscall(num));
+        }
+        self.syscalls = handler;
+    }
+}
@@ -0,0 +1,243 @@
+use super::*;
+use std::convert::TryFrom;
+use std::fs;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Component, Path, PathBuf};
+
+/// Returned in the result register by file syscalls that failed.
+pub const FILE_ERR: Word = !0;
+/// In-memory files can't grow past this many bytes, seeking past it fails.
+pub const MAX_MEM_FILE: usize = 1 << 24;
+
+pub mod fmode {
+    use super::Word;
+
+    pub const READ:   Word = 0;
+    pub const WRITE:  Word = 1;
+    pub const APPEND: Word = 2;
+    pub const UPDATE: Word = 3;
+}
+
+/// Where guest file syscalls are allowed to go.
+pub enum Vfs {
+    /// No filesystem, every `open` fails.
+    Closed,
+    /// Host directory, guest paths are resolved relative to it.
+    Host(PathBuf),
+    /// Files kept in memory, keyed by guest path.
+    Memory(HashMap<String, Vec<u8>>)
+}
+
+enum Handle {
+    Host(fs::File),
+    Memory { name: String, mode: Word, pos: usize }
+}
+
+/// Per-CPU open files; handle `n` is index `n` in `handles`.
+pub struct Files {
+    pub vfs: Vfs,
+    handles: Vec<Option<Handle>>
+}
+
+impl Files {
+    pub fn new(vfs: Vfs) -> Files {
+        Files{ vfs, handles: Vec::new() }
+    }
+
+    /// Only plain relative paths stay inside the sandbox.
+    fn sandboxed(path: &str) -> Option<&Path> {
+        let path = Path::new(path);
+        if path.as_os_str().is_empty() || !path.components().all(|c| matches!(c, Component::Normal(_))) {
+            return None;
+        }
+        Some(path)
+    }
+
+    /// Host path of `path` with symlinks resolved, `None` if that leaves
+    /// `root`. A file that doesn't exist yet only needs its directory
+    /// inside, a dangling symlink is refused.
+    fn resolve(root: &Path, path: &Path) -> Option<PathBuf> {
+        let root = root.canonicalize().ok()?;
+        let full = root.join(path);
+        let real = match full.canonicalize() {
+            Ok(real) => real,
+            Err(_) if fs::symlink_metadata(&full).is_ok() => return None,
+            Err(_) => full.parent()?.canonicalize().ok()?.join(full.file_name()?)
+        };
+        if real.starts_with(&root) { Some(real) } else { None }
+    }
+
+    pub fn open(&mut self, path: &str, mode: Word) -> Option<Word> {
+        let path = Files::sandboxed(path)?;
+        let handle = match &mut self.vfs {
+            Vfs::Closed => return None,
+            Vfs::Host(root) => {
+                let mut opts = fs::OpenOptions::new();
+                match mode {
+                    fmode::READ   => opts.read(true),
+                    fmode::WRITE  => opts.write(true).create(true).truncate(true),
+                    fmode::APPEND => opts.append(true).create(true),
+                    fmode::UPDATE => opts.read(true).write(true),
+                    _ => return None
+                };
+                Handle::Host(opts.open(Files::resolve(root, path)?).ok()?)
+            }
+            Vfs::Memory(files) => {
+                let name = path.to_string_lossy().into_owned();
+                let pos = match mode {
+                    fmode::READ | fmode::UPDATE => { files.get(&name)?; 0 }
+                    fmode::WRITE  => { files.insert(name.clone(), Vec::new()); 0 }
+                    fmode::APPEND => files.entry(name.clone()).or_default().len(),
+                    _ => return None
+                };
+                Handle::Memory{ name, mode, pos }
+            }
+        };
+
+        let num = match self.handles.iter().position(Option::is_none) {
+            Some(num) => num,
+            None => { self.handles.push(None); self.handles.len() - 1 }
+        };
+        self.handles[num] = Some(handle);
+        Some(num as Word)
+    }
+
+    fn handle(&mut self, num: Word) -> Option<&mut Handle> {
+        self.handles.get_mut(num as usize)?.as_mut()
+    }
+
+    pub fn read(&mut self, num: Word, buf: &mut [u8]) -> Option<usize> {
+        let files = match &mut self.vfs { Vfs::Memory(files) => Some(files), _ => None };
+        match self.handles.get_mut(num as usize)?.as_mut()? {
+            Handle::Host(f) => f.read(buf).ok(),
+            Handle::Memory{ name, mode, pos } => {
+                if *mode == fmode::WRITE || *mode == fmode::APPEND { return None; }
+                let data = files?.get(name.as_str())?;
+                let start = (*pos).min(data.len());
+                let cnt = buf.len().min(data.len() - start);
+                buf[..cnt].copy_from_slice(&data[start..start + cnt]);
+                *pos = start + cnt;
+                Some(cnt)
+            }
+        }
+    }
+
+    pub fn write(&mut self, num: Word, buf: &[u8]) -> Option<usize> {
+        let files = match &mut self.vfs { Vfs::Memory(files) => Some(files), _ => None };
+        match self.handles.get_mut(num as usize)?.as_mut()? {
+            Handle::Host(f) => f.write_all(buf).ok().map(|_| buf.len()),
+            Handle::Memory{ name, mode, pos } => {
+                let data = files?.get_mut(name.as_str())?;
+                match *mode {
+                    fmode::READ   => return None,
+                    fmode::APPEND => *pos = data.len(),
+                    _ => {}
+                }
+                let end = pos.checked_add(buf.len()).filter(|end| *end <= MAX_MEM_FILE)?;
+                if data.len() < end {
+                    data.resize(end, 0);
+                }
+                data[*pos..*pos + buf.len()].copy_from_slice(buf);
+                *pos += buf.len();
+                Some(buf.len())
+            }
+        }
+    }
+
+    /// `whence` is 0 for the start of the file, 1 for the current position
+    /// and 2 for the end, like `lseek`.
+    pub fn seek(&mut self, num: Word, off: i64, whence: Word) -> Option<u64> {
+        let len = match (&self.vfs, self.handles.get(num as usize)?.as_ref()?) {
+            (Vfs::Memory(files), Handle::Memory{ name, .. }) => files.get(name)?.len(),
+            _ => 0
+        };
+        match self.handle(num)? {
+            Handle::Host(f) => {
+                let from = match whence {
+                    0 => SeekFrom::Start(u64::try_from(off).ok()?),
+                    1 => SeekFrom::Current(off),
+                    2 => SeekFrom::End(off),
+                    _ => return None
+                };
+                f.seek(from).ok()
+            }
+            Handle::Memory{ pos, .. } => {
+                let base = match whence { 0 => 0, 1 => *pos as i64, 2 => len as i64, _ => return None };
+                let new = base.checked_add(off).filter(|new| (0..=MAX_MEM_FILE as i64).contains(new))?;
+                *pos = new as usize;
+                Some(new as u64)
+            }
+        }
+    }
+
+    pub fn close(&mut self, num: Word) -> bool {
+        match self.handles.get_mut(num as usize) {
+            Some(handle) => handle.take().is_some(),
+            None => false
+        }
+    }
+}
+
+impl CpuState {
+    /// Words of a syscall buffer, checked to allow `need`.
+    fn buffer(&mut self, adr: Word, len: Word, need: Word) -> Option<std::ops::Range<usize>> {
+        let range = adr as usize..adr as usize + len as usize;
+        if range.end > self.mem.len() {
+            self.fault(FaultKind::BadAddress(adr.max(self.mem.len() as Word)));
+            return None;
+        }
+        if !self.access(adr, range.end as Word, need) { return None; }
+        Some(range)
+    }
+
+    /// `rN` = path, `rN+1` = mode; `rN` gets the handle.
+    pub fn file_open(&mut self, reg: usize) {
+        if !self.check_regs(reg, 2) { return; }
+        if let Some(path) = self.read_str(self.r[reg]) {
+            self.r[reg] = self.files.open(&path, self.r[reg + 1]).unwrap_or(FILE_ERR);
+        }
+    }
+
+    /// `rN` = handle, `rN+1` = buffer, `rN+2` = max count; `rN` gets the
+    /// count read. Every byte is stored in its own word.
+    pub fn file_read(&mut self, reg: usize) {
+        if !self.check_regs(reg, 3) { return; }
+        let range = match self.buffer(self.r[reg + 1], self.r[reg + 2], perm::W) { Some(range) => range, None => return };
+
+        let mut bytes = vec![0u8; range.len()];
+        self.r[reg] = match self.files.read(self.r[reg], &mut bytes) {
+            Some(cnt) => {
+                for (cell, byte) in self.mem[range.clone()].iter_mut().zip(&bytes[..cnt]) {
+                    *cell = *byte as Word;
+                }
+                self.wrote(range.start as Word, (range.start + cnt) as Word);
+                cnt as Word
+            }
+            None => FILE_ERR
+        };
+    }
+
+    /// `rN` = handle, `rN+1` = buffer, `rN+2` = count; `rN` gets the count written.
+    pub fn file_write(&mut self, reg: usize) {
+        if !self.check_regs(reg, 3) { return; }
+        let range = match self.buffer(self.r[reg + 1], self.r[reg + 2], perm::R) { Some(range) => range, None => return };
+
+        let bytes: Vec<u8> = self.mem[range].iter().map(|c| *c as u8).collect();
+        self.r[reg] = self.files.write(self.r[reg], &bytes).map_or(FILE_ERR, |cnt| cnt as Word);
+    }
+
+    /// `rN` = handle, `rN+1` = signed offset, `rN+2` = whence; `rN` gets the new position.
+    pub fn file_seek(&mut self, reg: usize) {
+        if !self.check_regs(reg, 3) { return; }
+        let off = self.r[reg + 1] as i32 as i64;
+        self.r[reg] = match self.files.seek(self.r[reg], off, self.r[reg + 2]) {
+            Some(pos) if pos < FILE_ERR as u64 => pos as Word,
+            _ => FILE_ERR
+        };
+    }
+
+    /// `rN` = handle; `rN` gets 0 or `FILE_ERR`.
+    pub fn file_close(&mut self, reg: usize) {
+        self.r[reg] = if self.files.close(self.r[reg]) { 0 } else { FILE_ERR };
+    }
+}
@@ -7,6 +7,7 @@ impl CPU {
 	fn disasm_cmd(&self, cmd : &Word, labeltbl: &HashMap<u32, u32>) -> String {
 		if *cmd == 0 { return "word".to_owned(); }
 		let code = &getcode!(cmd);
+		if !self.table.has_code(code) { return format!("word {}", cmd); }
 		let name : String = self.table.get_name(code).to_owned();
 		let (_, fmt) = self.table.get_code(&name);
 		let args;
@@ -38,11 +39,26 @@ impl CPU {
 
 		name + " " + &args
 	}
-	pub fn disassemble(&self, mut f : File) {
+	/// One line about a store into code: who wrote where, and the
+	/// instruction it replaced with the one it put there.
+	pub fn describe(&self, store: &CodeStore) -> String {
+		let labeltbl = self.labels();
+		let decode = |word: Word| {
+			if self.table.has_code(&getcode!(word)) { self.disasm_cmd(&word, &labeltbl) }
+			else { format!("{:#010x}", word) }
+		};
+		let core = if store.core != 0 { format!(" on core {}", store.core) } else { String::new() };
+		format!("pc {}{} overwrote {}: {} -> {}", store.pc, core, store.adr, decode(store.old), decode(store.new))
+	}
+
+	/// Label numbers the disassembler gives to addresses: `label0` is the
+	/// entry point, the rest are `calli` targets in the code section in
+	/// order of appearance.
+	pub fn labels(&self) -> HashMap<u32, u32> {
 		let mut labeltbl : HashMap<u32, u32> = HashMap::new();
 		let mut labelcnt = 1;
 
-		for i in 0..self.state.progsz {
+		for i in 0..self.state.cnst_start.min(self.state.progsz) {
 			let line = self.state.mem[i as usize];
 				let code = getcode!(line);
 				
@@ -54,13 +70,24 @@ impl CPU {
 		}
 
 		labeltbl.insert(self.state.r[15], 0);
+		labeltbl
+	}
+
+	pub fn disassemble(&self, mut f : File) {
+		let labeltbl = self.labels();
 		for  i in 0..self.state.progsz {
+			if i == self.state.data_start {
+				f.write_all(b"section data\n").unwrap();
+			} else if i == self.state.cnst_start {
+				f.write_all(b"section const\n").unwrap();
+			}
 			if labeltbl.contains_key(&i) {
 				let lbl_str = format!("label{}:\n", labeltbl.get(&i).expect("Label error!"));
 				f.write_all(lbl_str.as_bytes()).unwrap();
 			}
 			let word = self.state.mem[i as usize];
-			let line = self.disasm_cmd(&word, &labeltbl) + "\n";
+			let line = if i < self.state.cnst_start { self.disasm_cmd(&word, &labeltbl) + "\n" }
+			           else { format!("word {}\n", word) };
 			f.write_all(line.as_bytes()).unwrap();
 		}
 
@@ -0,0 +1,93 @@
+use super::cpu::*;
+use std::io::Write;
+
+/// Prints a readelf-like report of a `ThisIsFUPM2Exec` image: header fields,
+/// section layout, symbol/debug info and everything that looks wrong in it.
+pub fn inspect(bytes: &[u8], table: &CmdTable, out: &mut dyn Write) {
+	macro_rules! outln {
+		($($arg:tt)*) => { writeln!(out, $($arg)*).expect("Unable to write report!") };
+	}
+
+	let header = match ExecHeader::parse(bytes) {
+		Some(header) => header,
+		None => {
+			outln!("Not a FUPM2 executable: bad magic or header shorter than {} bytes",
+				EXEC_MAGIC.len() + ExecHeader::FIELDS * 4);
+			return;
+		}
+	};
+
+	outln!("FUPM2 exec header:");
+	outln!("  Magic:            {}", String::from_utf8_lossy(&EXEC_MAGIC[..EXEC_MAGIC.len() - 1]));
+	outln!("  Code size:        {} words", header.prog_size);
+	outln!("  Const size:       {} words", header.cnst_size);
+	outln!("  Data size:        {} words", header.data_size);
+	outln!("  Entry point:      {:#07x} ({})", header.begn_addr, header.begn_addr);
+	outln!("  Stack pointer:    {:#07x} ({})", header.stck_addr, header.stck_addr);
+	let mem_words = match header.mem_words {
+		0 => { outln!("  Memory size:      default ({} words)", MEMSZ); MEMSZ }
+		n => { outln!("  Memory size:      {} words", n); n as usize }
+	};
+	outln!("  File size:        {} bytes", bytes.len());
+	outln!();
+
+	outln!("Sections:");
+	outln!("  {:<8}{:>10}{:>10}{:>10}  {}", "Name", "Offset", "End", "Words", "Flags");
+	outln!("  {:<8}{:>#10x}{:>#10x}{:>10}", "header", 0, EXEC_HDRSZ, "-");
+	let sections = header.sections();
+	for ((name, start, end), flags) in sections.iter().zip(&["R X", "R", "RW"]) {
+		outln!("  {:<8}{:>#10x}{:>#10x}{:>10}  {}", name, start, end, (end - start) / 4, flags);
+	}
+	outln!();
+
+	let image_end = sections[2].2;
+	outln!("Symbols: none (not stored by this format)");
+	if (bytes.len() as u64) > image_end {
+		outln!("Debug info: {} unrecognized trailing bytes at {:#x}",
+			bytes.len() as u64 - image_end, image_end);
+	} else {
+		outln!("Debug info: none");
+	}
+	outln!();
+
+	let mut problems: Vec<String> = Vec::new();
+	if (bytes.len() as u64) < image_end {
+		problems.push(format!("file is truncated: sections end at {:#x}, file at {:#x}",
+			image_end, bytes.len()));
+	}
+	if mem_words > MAX_MEM_WORDS {
+		problems.push(format!("memory size {} words is above the {} words allowed",
+			mem_words, MAX_MEM_WORDS));
+	}
+	if header.image_size() as usize > mem_words {
+		problems.push(format!("sections ({} words) do not fit into memory ({} words)",
+			header.image_size(), mem_words));
+	}
+	if header.begn_addr >= header.prog_size {
+		problems.push(format!("entry point {} is outside code [0, {})",
+			header.begn_addr, header.prog_size));
+	}
+	if header.stck_addr as usize > mem_words {
+		problems.push(format!("stack pointer {} is outside memory [0, {}]",
+			header.stck_addr, mem_words));
+	}
+
+	let (_, code_start, code_end) = sections[0];
+	let code_end = code_end.min(bytes.len() as u64) as usize;
+	let code = &bytes[code_start.min(code_end as u64) as usize..code_end];
+	for (adr, chunk) in code.chunks_exact(4).enumerate() {
+		let word = get_word!(chunk);
+		let code = getcode!(word);
+		if !table.has_code(&code) {
+			problems.push(format!("unknown opcode {} at address {} (word {:#010x})", code, adr, word));
+		}
+	}
+
+	outln!("Validation:");
+	if problems.is_empty() {
+		outln!("  OK");
+	}
+	for problem in problems {
+		outln!("  {}", problem);
+	}
+}
@@ -0,0 +1,12 @@
+#![allow(clippy::upper_case_acronyms)]
+// Kept in the style the code was written in.
+#![allow(clippy::expect_fun_call, clippy::needless_late_init, clippy::print_with_newline,
+         clippy::single_match, clippy::clone_on_copy)]
+
+#[macro_use]
+pub mod cpu;
+pub mod txtparse;
+pub mod inspect;
+mod procexec;
+mod disasm;
+mod patch;
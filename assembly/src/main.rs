@@ -1,20 +1,270 @@
-#[macro_use]
-mod cpu;
-mod txtparse;
-mod procexec;
-mod disasm;
+use assembly::{cpu, inspect, txtparse};
+use std::{env, fs, fs::File, fs::OpenOptions, io, io::Write, process};
 
-use std::{fs, fs::File};
+const USAGE: &str = "\
+Usage: assembly <command> <args>
+
+Commands:
+    asm [--mem <words>] <input.fasm> <output.fbin>
+                                         assemble source into an executable,
+                                         optionally asking for a memory size
+    disasm <input.fbin> <output.fasm>    disassemble an executable
+    run [options] <input.fbin> [args...] execute an executable with argc in r0,
+                                         argv in r1 and envp in r2; exits with
+                                         the program's status, 255 on a fault
+                                         or 124 when the step limit is hit
+    inspect <input.fbin>                 print header, sections and a validation report
+    patch <file.fbin> <patch>            modify an executable in place
+
+Patches (<at> is an address or a disassembler label like label2):
+    cmd <at> <source line...>            replace the instruction at <at>
+    entry <at>                           change the entry point
+    poke <at> <value>                    overwrite a data word
+
+Run options:
+    --mem <words>                        memory size unless the executable asks for one
+    --limit <n>                          stop after <n> instructions
+    --stats                              print instruction and cycle counts
+    --watch-code                         report stores that overwrite code
+    --root <dir>                         confine file syscalls to <dir>
+    --env <NAME=value>                   add a variable to the guest environment
+    --seed <n>                           seed the guest random number generator
+    --engine <interp|blocks>             execution engine, interp by default
+    --protect <on|log>                   read-only code and constants, no executing
+                                         data, a guard gap above the heap; log
+                                         only reports violations when done
+    --schedule <rr:<n>|seed:<n>>         how cores take turns: round-robin with
+                                         <n> instructions each (rr:100 by
+                                         default) or seeded random interleaving
+    --device <name>@<adr>                map a device into memory: console,
+                                         timer or screen<W>x<H>
+
+A program may exit with 124 itself. A fault always ends stderr with a line
+starting with \"FAULT: \" and the step limit with \"STEP LIMIT: \", a program
+that exits by itself prints neither. Only statuses 0 to 254 are passed on:
+larger ones (and negative ones) exit with 125 after a line starting with
+\"EXIT STATUS: \" that gives the real one.";
+
+/// Exit statuses of `run` when the program doesn't exit by itself or
+/// with a status the OS can't pass on. Guests can exit with some of them
+/// too, the tagged lines on stderr tell the cases apart.
+const FAULT_STATUS: i32 = 255;
+const LIMIT_STATUS: i32 = 124;
+const RANGE_STATUS: i32 = 125;
+const FAULT_TAG: &str = "FAULT: ";
+const LIMIT_TAG: &str = "STEP LIMIT: ";
+const RANGE_TAG: &str = "EXIT STATUS: ";
+/// Largest guest status passed on as it is.
+const MAX_STATUS: u32 = 254;
+
+/// Options that don't take a value.
+const FLAGS: &[&str] = &["--stats", "--watch-code"];
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(2);
+}
+
+/// Splits leading `--option value` pairs from the rest of the arguments.
+fn split_opts<'a, 'b>(mut args: &'b [&'a str]) -> (Vec<(&'a str, &'a str)>, &'b [&'a str]) {
+    let mut opts = Vec::new();
+    while let [opt, tail @ ..] = args {
+        if !opt.starts_with("--") { break; }
+        if FLAGS.contains(opt) {
+            opts.push((*opt, ""));
+            args = tail;
+            continue;
+        }
+        match tail {
+            [val, tail @ ..] => { opts.push((*opt, *val)); args = tail; }
+            _ => usage()
+        }
+    }
+    (opts, args)
+}
+
+fn parse_num(num: &str) -> Option<u32> {
+    match num.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => num.parse().ok()
+    }
+}
+
+fn parse_mem(words: &str) -> usize {
+    parse_num(words).map(|words| words as usize)
+        .filter(|words| *words <= cpu::MAX_MEM_WORDS)
+        .unwrap_or_else(|| panic!("Bad memory size! ({}, at most {})", words, cpu::MAX_MEM_WORDS))
+}
+
+fn parse_device(spec: &str) -> (Box<dyn cpu::Device>, u32) {
+    let mut parts = spec.splitn(2, '@');
+    let name = parts.next().unwrap_or("");
+    let adr = parts.next().and_then(parse_num)
+        .unwrap_or_else(|| panic!("Bad device address! ({})", spec));
+
+    let dev: Box<dyn cpu::Device> = match name {
+        "console" => Box::new(cpu::Console::stdio()),
+        "timer"   => Box::new(cpu::Timer::default()),
+        _ => {
+            let size = name.strip_prefix("screen")
+                .and_then(|size| {
+                    let mut dims = size.splitn(2, 'x').map(parse_num);
+                    Some((dims.next()??, dims.next()??))
+                })
+                .unwrap_or_else(|| panic!("Unknown device! ({})", name));
+            Box::new(cpu::Screen::stdout(size.0, size.1)
+                .unwrap_or_else(|| panic!("Screen too large! ({})", name)))
+        }
+    };
+    (dev, adr)
+}
+
+fn parse_schedule(spec: &str) -> cpu::Schedule {
+    let num = |n: &str| n.parse().unwrap_or_else(|_| panic!("Bad schedule! ({})", spec));
+    match spec.split_once(':') {
+        Some(("rr", n))   => cpu::Schedule::RoundRobin(num(n) as u32),
+        Some(("seed", n)) => cpu::Schedule::Seeded(num(n)),
+        _ => panic!("Bad schedule! ({})", spec)
+    }
+}
 
 fn main() {
-	// let prog = fs::read_to_string("input.fasm").expect("File read error");
-    // let mut f = File::create("binary.basm").expect("Unable to open file for reading!");
-    // let mut res = txtparse::parsecode(&prog);
-    // res.save(&mut f);
- 	let mut f = File::open("exec.fbin").expect("Unable to open file for reading!");
- 	let mut res = cpu::CPU::new();
-    res.load(&mut f);
-	let parsed_prog = fs::File::create("disasm.fasm").expect("Unable to create file!");
-    res.disassemble(parsed_prog);
-    // res.exec()
-}
\ No newline at end of file
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    match args.as_slice() {
+        ["asm", rest @ ..] => {
+            let (opts, files) = split_opts(rest);
+            let mut cfg = cpu::MachineConfig::default();
+            for (opt, val) in opts {
+                match opt {
+                    "--mem" => cfg.mem_words = parse_mem(val),
+                    _ => usage()
+                }
+            }
+            let (input, output) = match files {
+                [input, output] => (input, output),
+                _ => usage()
+            };
+
+            let prog = fs::read_to_string(input).expect("File read error");
+            let res = txtparse::parsecode(&prog, cfg);
+            let mut f = File::create(output).expect("Unable to open file for writing!");
+            res.save(&mut f);
+        }
+        ["disasm", input, output] => {
+            let mut f = File::open(input).expect("Unable to open file for reading!");
+            let mut res = cpu::CPU::default();
+            res.load(&mut f);
+            let parsed_prog = File::create(output).expect("Unable to create file!");
+            res.disassemble(parsed_prog);
+        }
+        ["run", rest @ ..] => {
+            let (opts, prog_args) = split_opts(rest);
+            if prog_args.is_empty() { usage(); }
+
+            let mut cfg = cpu::MachineConfig::default();
+            for (opt, val) in &opts {
+                match *opt {
+                    "--mem" => cfg.mem_words = parse_mem(val),
+                    "--schedule" => cfg.schedule = parse_schedule(val),
+                    "--watch-code" => cfg.watch_code = true,
+                    "--protect" => cfg.protection = match *val {
+                        "on"  => cpu::Protection::Enforce,
+                        "log" => cpu::Protection::Log,
+                        _ => panic!("Unknown protection mode! ({})", val)
+                    },
+                    _ => {}
+                }
+            }
+
+            let mut res = cpu::CPU::new(cfg);
+            let mut env: Vec<&str> = Vec::new();
+            let mut limit = u64::MAX;
+            let mut stats = false;
+            for (opt, val) in opts {
+                match opt {
+                    "--mem" | "--schedule" | "--protect" | "--watch-code" => {}
+                    "--limit" => limit = val.parse().expect("Bad step limit!"),
+                    "--stats" => stats = true,
+                    "--root" => res.state.files = cpu::Files::new(cpu::Vfs::Host(val.into())),
+                    "--env"  => env.push(val),
+                    "--seed" => res.state.rng.seed(val.parse().expect("Bad seed!")),
+                    "--engine" => res.engine = match val {
+                        "interp" => cpu::Engine::Interp,
+                        "blocks" => cpu::Engine::Blocks,
+                        _ => panic!("Unknown engine! ({})", val)
+                    },
+                    "--device" => {
+                        let (dev, adr) = parse_device(val);
+                        res.state.bus.map(adr, dev);
+                    }
+                    _ => usage()
+                }
+            }
+
+            let mut f = File::open(prog_args[0]).expect("Unable to open file for reading!");
+            res.load(&mut f);
+            res.state.set_args(prog_args, &env);
+            let outcome = res.exec_with_limit(limit);
+            io::stdout().flush().ok();
+            if stats {
+                eprintln!("{} instructions, {} cycles", res.state.steps, res.state.cycles);
+            }
+            for violation in &res.state.violations {
+                eprintln!("PROTECTION: {}", violation);
+            }
+            for store in &res.state.code_stores {
+                eprintln!("CODE WRITE: {}", res.describe(store));
+            }
+            match outcome {
+                cpu::Outcome::Halted(status) if status <= MAX_STATUS => process::exit(status as i32),
+                cpu::Outcome::Halted(status) => {
+                    eprintln!("{}{} doesn't fit, exiting with {}", RANGE_TAG, status as i32, RANGE_STATUS);
+                    process::exit(RANGE_STATUS);
+                }
+                cpu::Outcome::Fault(fault) => {
+                    eprintln!("{}{}", FAULT_TAG, fault);
+                    process::exit(FAULT_STATUS);
+                }
+                cpu::Outcome::StepLimit => {
+                    eprintln!("{}stopped after {} instructions", LIMIT_TAG, res.state.steps);
+                    process::exit(LIMIT_STATUS);
+                }
+            }
+        }
+        ["inspect", input] => {
+            let bytes = fs::read(input).expect("Unable to open file for reading!");
+            let table = cpu::CmdTable::new();
+            inspect::inspect(&bytes, &table, &mut io::stdout());
+        }
+        ["patch", file, patch @ ..] => {
+            let mut f = OpenOptions::new().read(true).write(true).open(file)
+                .expect("Unable to open file for patching!");
+            let mut res = cpu::CPU::default();
+            res.load(&mut f);
+
+            match patch {
+                ["cmd", at, line @ ..] if !line.is_empty() => {
+                    let adr = res.resolve(at);
+                    res.patch_cmd(adr, &line.join(" "));
+                }
+                ["entry", at] => {
+                    let adr = res.resolve(at);
+                    res.patch_entry(adr);
+                }
+                ["poke", at, value] => {
+                    let adr = res.resolve(at);
+                    let val = match value.parse::<u32>() {
+                        Ok(val) => val,
+                        Err(_) => value.parse::<i32>().expect("Bad poke value!") as u32
+                    };
+                    res.poke(adr, val);
+                }
+                _ => usage()
+            }
+            res.update(&mut f);
+        }
+        _ => usage()
+    }
+}
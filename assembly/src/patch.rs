@@ -0,0 +1,57 @@
+use super::cpu::*;
+use super::txtparse::makeword;
+use std::collections::HashMap;
+
+impl CPU {
+	/// Turns a patch target into an address: either a plain number or a
+	/// `labelN` name as printed by the disassembler.
+	pub fn resolve(&self, target: &str) -> u32 {
+		if let Ok(adr) = target.parse::<u32>() {
+			return adr;
+		}
+
+		self.labels().iter()
+			.find(|(_, num)| format!("label{}", num) == target)
+			.map(|(adr, _)| *adr)
+			.unwrap_or_else(|| panic!("Invalid label! ({})", target))
+	}
+
+	fn check_adr(&self, adr: u32) {
+		if adr >= self.state.progsz {
+			panic!("Address {} is outside the loaded image [0, {})", adr, self.state.progsz);
+		}
+	}
+
+	/// Assembles a single source line and puts it at `adr`.
+	/// Memory arguments may use disassembler labels.
+	pub fn patch_cmd(&mut self, adr: u32, line: &str) {
+		self.check_adr(adr);
+
+		let names: Vec<(String, u32)> = self.labels().iter()
+			.map(|(adr, num)| (format!("label{}", num), *adr))
+			.collect();
+		let labeltabel: HashMap<&str, u32> = names.iter()
+			.map(|(name, adr)| (name.as_str(), *adr))
+			.collect();
+
+		let toks: Vec<&str> = line.split_whitespace().collect();
+		if toks.is_empty() { panic!("Empty patch line!"); }
+		self.state.mem[adr as usize] = makeword(toks, &self.table, &labeltabel);
+		self.state.wrote(adr, adr + 1);
+	}
+
+	/// The entry point has to be in the code section, constants and data
+	/// can't be executed.
+	pub fn patch_entry(&mut self, adr: u32) {
+		if adr >= self.state.cnst_start {
+			panic!("Entry {} is outside the code section [0, {})", adr, self.state.cnst_start);
+		}
+		self.state.r[15] = adr;
+	}
+
+	pub fn poke(&mut self, adr: u32, val: Word) {
+		self.check_adr(adr);
+		self.state.mem[adr as usize] = val;
+		self.state.wrote(adr, adr + 1);
+	}
+}
@@ -1,33 +1,33 @@
 use super::cpu::*;
 
 impl CPU {
-	fn docmd(&mut self, cmd: &Word) {
-		let code = &getcode!(cmd);
+	fn docmd(&mut self, instr: &Instr) {
+		let cmd = match self.table.get(instr.code) {
+			Some(cmd) => cmd,
+			None => return self.state.fault(FaultKind::BadOpcode(instr.code))
+		};
 		if self.state.mode & dbmode::CMD != 0 {
-			let name = self.table.get_name(code);
-			println!("CMD=({})", name);
+			println!("CMD=({})", cmd.name);
 			if self.state.mode & dbmode::ARG != 0 {
-				let (_, fmt) = self.table.get_code(name);
-				let args;
-				match fmt {
-					CmdFormat::RR   => args = format!("{:?}", prs!(RR => cmd)),
-					CmdFormat::RI   => args = format!("{:?}", prs!(RI => cmd)),
-					CmdFormat::RM   => args = format!("{:?}", prs!(RM => cmd)),
-					CmdFormat::JMEM => args = format!("{:?}", prs!(JM => cmd))
-				}
+				let args = match cmd.format {
+					CmdFormat::RR   => format!("{:?}", instr.rr()),
+					CmdFormat::RI   => format!("{:?}", instr.ri()),
+					CmdFormat::RM   => format!("{:?}", instr.rm()),
+					CmdFormat::JMEM => format!("{:?}", instr.jm())
+				};
 
 				println!("ARGS=({})", args);
 			}
 		}
 
-		let func = self.table.get_func(code);
-		func(&mut self.state, cmd);
+		(cmd.func)(&mut self.state, instr);
+		self.state.cycles += cmd.cycles as u64;
 
 		if self.state.mode & dbmode::REG != 0 {
 			print!("REG=");
 			for i in 0..16 {
 				if i == 14 {
-					print!("(STPTR={})", MEMSZ as u32 - self.state.r[i]);
+					print!("(STPTR={})", (self.state.mem.len() as u32).wrapping_sub(self.state.r[i]));
 				}
 				print!("{}:{} | ", i, self.state.r[i]);
 
@@ -35,11 +35,101 @@ impl CPU {
 			print!("\n\n")
 		}
 	}
-	pub fn exec(&mut self) {
+	/// Passes a pending fault to the guest handler if one is set: the faulting
+	/// pc and the fault code are pushed and execution resumes at the handler.
+	/// A fault while entering the handler stops the CPU with the original one.
+	fn trap(&mut self) {
+		let (fault, handler) = match (self.state.fault, self.state.fault_handler) {
+			(Some(fault), Some(handler)) => (fault, handler),
+			_ => return
+		};
+
+		self.state.fault = None;
+		self.state.halt = false;
+		self.state.push(fault.pc);
+		self.state.push(fault.kind.code());
+		if self.state.fault.is_some() {
+			self.state.fault = Some(fault);
+			return;
+		}
+		self.state.jump(handler);
+	}
+
+	pub fn step(&mut self) {
+		let pc = self.state.r[15];
+		match self.state.mem.get(pc as usize).cloned() {
+			Some(_) if !self.state.access(pc, pc + 1, perm::X) => {}
+			Some(word) => match self.decoded.fetch(&self.table, self.state.progsz, pc, word) {
+				Some(instr) => self.docmd(&instr),
+				None        => self.state.fault(FaultKind::BadOpcode(getcode!(word)))
+			},
+			None        => self.state.fault(FaultKind::BadAddress(pc))
+		}
+		self.retire();
+	}
+
+	/// Bookkeeping after every instruction, whichever engine ran it.
+	fn retire(&mut self) {
+		self.state.steps += 1;
+		if !self.state.bus.is_empty() {
+			self.state.irq |= self.state.bus.tick();
+		}
+
+		if self.state.fault.is_some() {
+			self.trap();
+		}
+		self.state.r[15] = self.state.r[15].wrapping_add(1);
+		self.state.interrupt();
+	}
+
+	/// Runs the cached block at pc, leaving it as soon as control goes
+	/// anywhere but the next command in it. Debug modes and code outside
+	/// the loaded image go through `step`.
+	fn run_block(&mut self, limit: u64) {
+		let pc = self.state.r[15];
+		if self.state.mode != 0 {
+			return self.step();
+		}
+		let block = match self.blocks.get(&self.table, &mut self.state, pc) {
+			Some(block) => block,
+			None => return self.step()
+		};
+
+		for (i, op) in block.ops.iter().enumerate() {
+			(op.run)(&mut self.state);
+			self.state.cycles += op.cycles as u64;
+			self.retire();
+
+			let next = pc.wrapping_add(i as Word + 1);
+			if self.state.halt || self.state.steps >= limit || self.state.r[15] != next
+				|| !self.state.code_writes.is_empty() {
+				break;
+			}
+		}
+	}
+
+	pub fn exec(&mut self) -> Outcome {
+		self.exec_with_limit(u64::MAX)
+	}
+
+	/// Runs at most `max_steps` more instructions. `state.steps` and
+	/// `state.cycles` keep the totals across calls.
+	pub fn exec_with_limit(&mut self, max_steps: u64) -> Outcome {
+		let limit = self.state.steps.saturating_add(max_steps);
 		while !self.state.halt {
-			let word = self.state.mem[self.state.r[15] as usize];
-			self.docmd(&word);
-			self.state.r[15] = self.state.r[15].wrapping_add(1);
+			if self.state.steps >= limit {
+				return Outcome::StepLimit;
+			}
+			let slice = self.state.schedule(limit);
+			match self.engine {
+				Engine::Interp => self.step(),
+				Engine::Blocks => self.run_block(slice)
+			}
+		}
+
+		match self.state.fault {
+			Some(fault) => Outcome::Fault(fault),
+			None        => Outcome::Halted(self.state.status)
 		}
 	}
 }
\ No newline at end of file
@@ -35,14 +35,14 @@ pub fn makeword(toks: Vec<&str>, cmd_table: &CmdTable, labeltabel: &HashMap<&str
             let reg2 : u32 = partok!(r => 2);
             let imm  : u32 = partok!(i => 3);
 
-            ((cmd_data.0 as u32) << 24) + (reg1 << 20) + (reg2 << 16) + (imm & ((2 << 16) - 1))
+            ((cmd_data.0 as u32) << 24) + (reg1 << 20) + (reg2 << 16) + (imm & ((1 << 16) - 1))
         },
         CmdFormat::RI => {
             checkargs!(2);
             let reg : u32 = partok!(r => 1);
             let imm : u32 = partok!(i => 2);
 
-            ((cmd_data.0 as u32) << 24) + (reg << 20) + (imm & ((2 << 20) - 1))
+            ((cmd_data.0 as u32) << 24) + (reg << 20) + (imm & ((1 << 20) - 1))
         },
         CmdFormat::JMEM => {
             checkargs!(1);
@@ -53,8 +53,101 @@ pub fn makeword(toks: Vec<&str>, cmd_table: &CmdTable, labeltabel: &HashMap<&str
     }
 }
 
-pub fn parsecode(code: &str) -> CPU {
-    let mut cpu = CPU::new();
+/// Cuts a `;` comment off, ignoring `;` inside string literals.
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            _ if escaped  => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"'           => quoted = !quoted,
+            ';' if !quoted => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// Words emitted by a data directive, `None` if `line` isn't one.
+/// `word` stores a zero and `word 42` or `word -1` the given value,
+/// `string "text"` stores one character per word followed by a zero,
+/// `pstring "text"` packs ASCII ones four per word like `read_pstr` expects,
+/// `double 1.5` stores a double the way `load2` and `scand` expect it.
+pub fn parsedata(line: &str) -> Option<Vec<Word>> {
+    match line.split_whitespace().next() {
+        Some("word") => Some(parseword(line)),
+        Some("string") => Some(parsestring(line, "string")),
+        Some("pstring") => {
+            let chars = parsestring(line, "pstring");
+            if chars.iter().any(|c| *c > 0x7f) {
+                panic!("Non-ASCII string literal! ({})", line);
+            }
+            Some(chars.chunks(4).map(|c| c.iter().rev().fold(0, |word, &c| (word << 8) | c)).collect())
+        }
+        Some("double") => Some(parsedouble(line)),
+        _ => None
+    }
+}
+
+/// Characters of the string literal after `directive`, with the terminator.
+fn parsestring(line: &str, directive: &str) -> Vec<Word> {
+    let text = line.trim_start()[directive.len()..].trim();
+    if text.len() < 2 || !text.starts_with('"') || !text.ends_with('"') {
+        panic!("Bad string literal! ({})", line);
+    }
+
+    let mut words = Vec::new();
+    let mut chars = text[1..text.len() - 1].chars();
+    while let Some(c) = chars.next() {
+        let c = match c {
+            '\\' => match chars.next() {
+                Some('n') => '\n',
+                Some('t') => '\t',
+                Some('0') => '\0',
+                Some(c)   => c,
+                None      => panic!("Bad string literal! ({})", line)
+            },
+            c => c
+        };
+        words.push(c as Word);
+    }
+    words.push(0);
+
+    words
+}
+
+fn parseword(line: &str) -> Vec<Word> {
+    let toks: Vec<&str> = line.split_whitespace().collect();
+    let val = match toks.as_slice() {
+        [_] => 0,
+        [_, val] => val.parse::<u32>().or_else(|_| val.parse::<i32>().map(|val| val as u32))
+            .unwrap_or_else(|_| panic!("Bad word literal! ({})", line)),
+        _ => panic!("Bad word literal! ({})", line)
+    };
+    vec![val]
+}
+
+fn parsedouble(line: &str) -> Vec<Word> {
+    let toks: Vec<&str> = line.split_whitespace().collect();
+    let val: f64 = match toks.as_slice() {
+        [_, val] => val.parse().unwrap_or_else(|_| panic!("Bad double literal! ({})", line)),
+        _ => panic!("Bad double literal! ({})", line)
+    };
+
+    let bits = val.to_bits();
+    vec![bits as Word, (bits >> 32) as Word]
+}
+
+pub fn parsecode(code: &str, cfg: MachineConfig) -> CPU {
+    let mut cpu = CPU::new(cfg);
+    assemble(&mut cpu, code);
+    cpu
+}
+
+/// Assembles `code` into a prepared CPU, so commands added to its table
+/// with `CmdTable::define` can be used in the source.
+pub fn assemble(cpu: &mut CPU, code: &str) {
     let mut lines = code.lines();
     if code.starts_with('$') {
         let flags;
@@ -69,16 +162,18 @@ pub fn parsecode(code: &str) -> CPU {
     }
     let mut labeltabel : HashMap<&str, u32> = HashMap::new();
     let mut labeled = false;
-    let mut cmdnum : u32 = 0;
+    let mut cmdnum : u32;
+    let mut sections : [Option<u32>; 2] = [None; 2];
 
     while {
         cmdnum = 0;
         for line in lines.clone() {
             //Remove comments
-            let mut line = line.split_terminator(';').nth(0).unwrap().trim();
+            let mut line = strip_comment(line).trim();
 
             //Check if label
-            match line.find(':') {
+            let head = &line[..line.find('"').unwrap_or(line.len())];
+            match head.find(':') {
                 Some(size) =>  {
                     if !labeled {
                         labeltabel.insert(&line[0..size], cmdnum);
@@ -92,7 +187,28 @@ pub fn parsecode(code: &str) -> CPU {
 
             if line.chars().all(char::is_whitespace) { continue; }
 
-            if labeled && line != "word" {
+            //`section const` and `section data` only mark where those start
+            let toks : Vec<&str> = line.split_whitespace().collect();
+            if toks[0] == "section" {
+                let idx = match toks.get(1) {
+                    Some(&"const") => 0,
+                    Some(&"data")  => 1,
+                    _ => panic!("Unknown section! ({})", line)
+                };
+                sections[idx] = Some(cmdnum);
+                continue;
+            }
+
+            if let Some(data) = parsedata(line) {
+                if labeled {
+                    let start = cmdnum as usize;
+                    cpu.state.mem[start..start + data.len()].copy_from_slice(&data);
+                }
+                cmdnum += data.len() as u32;
+                continue;
+            }
+
+            if labeled {
                 let toks : Vec<&str> = line.split_whitespace().collect();
                 if toks[0] == "end" {
                     cpu.state.r[15] = *labeltabel.get(toks[1])
@@ -106,8 +222,14 @@ pub fn parsecode(code: &str) -> CPU {
         !labeled
     } { labeled = true }
 
+    let data_start = sections[1].unwrap_or(cmdnum);
+    let cnst_start = sections[0].unwrap_or(data_start);
+    if cnst_start > data_start {
+        panic!("Sections must come in order: code, const, data!");
+    }
+    cpu.state.cnst_start = cnst_start;
+    cpu.state.data_start = data_start;
     cpu.state.progsz = cmdnum;
-    cpu.state.r[14] = MEMSZ as Word;
-
-    cpu
+    cpu.state.brk = cmdnum;
+    cpu.state.wrote(0, cmdnum);
 }
\ No newline at end of file
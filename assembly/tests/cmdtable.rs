@@ -0,0 +1,12 @@
+use assembly::cpu::{CmdFormat, CmdTable, DefineError};
+
+#[test]
+fn define_refuses_taken_names_and_codes() {
+    let mut table = CmdTable::new();
+    let code = table.free_code().unwrap();
+    assert_eq!(table.define("sq", code, CmdFormat::RI, |_, _| {}), Ok(()));
+    assert_eq!(table.define("sq", 250, CmdFormat::RI, |_, _| {}), Err(DefineError::NameTaken("sq".to_owned())));
+    assert_eq!(table.define("halt2", code, CmdFormat::RI, |_, _| {}), Err(DefineError::CodeTaken(code)));
+    assert_eq!(table.define("halt", 250, CmdFormat::RI, |_, _| {}), Err(DefineError::NameTaken("halt".to_owned())));
+    assert!(!table.has_code(&250));
+}
@@ -0,0 +1,20 @@
+//! Programs shared by several test files.
+
+/// Runs `target` twice, storing the `addi` at `new` over it after the
+/// first time.
+pub const SELF_MODIFYING: &str = "
+main:
+    lc r0 0
+    lc r2 0
+target:
+    addi r0 1
+    load r1 new
+    store r1 target
+    addi r2 1
+    cmpi r2 2
+    jl target
+    halt r0 0
+new:
+    addi r0 100
+end main
+";
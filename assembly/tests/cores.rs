@@ -0,0 +1,114 @@
+use assembly::cpu::{Engine, MachineConfig, Outcome, Schedule, CPU};
+use assembly::txtparse;
+
+/// Core 0 gets 64 words from `sbrk` and starts a core with its stack at
+/// their top. The worker pushes and pops there, core 0 exits with what it
+/// popped.
+const HEAP_STACK: &str = "
+main:
+    lc r0 64
+    syscall r0 120
+    lc r1 0
+    add r1 r0 64
+    lc r0 10
+    syscall r0 141
+wait:
+    load r2 result
+    cmpi r2 0
+    jeq wait
+    syscall r2 1
+worker:
+    lc r3 42
+    push r3 0
+    pop r4 0
+    store r4 result
+    syscall r0 142
+result:
+    word
+end main
+";
+
+/// Two cores add 1 a hundred times each to the word at 24 with `xadd`
+/// and to the one at 25 with a `cas` loop. The second core sets the word
+/// at 26 when done, the first waits for it and halts.
+const COUNTERS: &str = "
+main:
+    lc r0 3
+    lc r1 500000
+    syscall r0 141
+work:
+    lc r6 100
+loop:
+    lc r0 1
+    xadd r0 r7 24
+retry:
+    load r0 25
+    lc r1 1
+    add r1 r0 0
+    cas r0 r7 25
+    jne retry
+    subi r6 1
+    cmpi r6 0
+    jg loop
+    syscall r5 140
+    cmpi r5 0
+    jeq main_wait
+    lc r0 1
+    store r0 26
+    syscall r0 142
+main_wait:
+    load r0 26
+    cmpi r0 0
+    jeq main_wait
+    halt r0 0
+    word
+    word
+    word
+end main
+";
+
+fn run(src: &str, schedule: Schedule, engine: Engine) -> (CPU, Outcome) {
+    let mut cpu = txtparse::parsecode(src, MachineConfig{ schedule, engine, ..MachineConfig::default() });
+    let outcome = cpu.exec_with_limit(100000);
+    (cpu, outcome)
+}
+
+#[test]
+fn worker_stack_may_live_in_the_heap() {
+    let (cpu, outcome) = run(HEAP_STACK, Schedule::RoundRobin(3), Engine::Interp);
+    assert!(matches!(outcome, Outcome::Halted(42)), "{:?}", outcome);
+    assert_eq!(cpu.state.cores.all.len(), 2);
+}
+
+#[test]
+fn heap_still_stops_at_the_main_stack() {
+    let src = "main:\n    syscall r14 120\n    halt r0 0\nend main\n";
+    let (_, outcome) = run(src, Schedule::RoundRobin(3), Engine::Interp);
+    assert!(matches!(outcome, Outcome::Fault(_)), "{:?}", outcome);
+}
+
+#[test]
+fn atomics_count_exactly_under_every_schedule() {
+    let schedules = [Schedule::RoundRobin(1), Schedule::RoundRobin(7), Schedule::Seeded(1), Schedule::Seeded(99)];
+    for schedule in &schedules {
+        for engine in &[Engine::Interp, Engine::Blocks] {
+            let (cpu, outcome) = run(COUNTERS, *schedule, *engine);
+            assert!(matches!(outcome, Outcome::Halted(0)), "{:?} {:?}", schedule, outcome);
+            assert_eq!(&cpu.state.mem[24..27], &[200, 200, 1], "{:?} {:?}", schedule, engine);
+        }
+    }
+}
+
+#[test]
+fn schedules_replay_the_same_interleaving() {
+    for schedule in &[Schedule::RoundRobin(5), Schedule::Seeded(7)] {
+        let (a, _) = run(COUNTERS, *schedule, Engine::Interp);
+        let (b, _) = run(COUNTERS, *schedule, Engine::Interp);
+        let (c, _) = run(COUNTERS, *schedule, Engine::Blocks);
+        assert_eq!(a.state.steps, b.state.steps);
+        assert_eq!(a.state.steps, c.state.steps);
+    }
+    let (a, _) = run(COUNTERS, Schedule::Seeded(7), Engine::Interp);
+    let (b, _) = run(COUNTERS, Schedule::Seeded(8), Engine::Interp);
+    assert_ne!(a.state.steps, b.state.steps);
+}
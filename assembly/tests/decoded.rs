@@ -0,0 +1,23 @@
+use assembly::cpu::{MachineConfig, Outcome};
+use assembly::txtparse;
+
+mod common;
+use common::SELF_MODIFYING;
+
+#[test]
+fn store_into_code_runs_the_new_command() {
+    let mut cpu = txtparse::parsecode(SELF_MODIFYING, MachineConfig::default());
+    assert!(matches!(cpu.exec(), Outcome::Halted(0)));
+    assert_eq!(cpu.state.r[0], 101);
+}
+
+#[test]
+fn host_patch_runs_the_new_command() {
+    let mut cpu = txtparse::parsecode("main:\n    addi r0 1\n    jmp main\nend main\n", MachineConfig::default());
+    cpu.exec_with_limit(4);
+    assert_eq!(cpu.state.r[0], 2);
+
+    cpu.patch_cmd(0, "addi r0 10");
+    cpu.exec_with_limit(4);
+    assert_eq!(cpu.state.r[0], 22);
+}
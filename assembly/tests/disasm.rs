@@ -0,0 +1,36 @@
+use assembly::cpu::MachineConfig;
+use assembly::txtparse;
+use std::{env, fs, fs::File};
+
+const SECTIONS: &str = "
+main:
+    load2 r0 half
+    calli f
+    halt r0 0
+f:
+    ret 0
+    word 4026531840
+section const
+half:
+    double 0.5
+    pstring \"abc\"
+section data
+    word -7
+    word 4026531840
+end main
+";
+
+#[test]
+fn disassembly_assembles_back() {
+    let cpu = txtparse::parsecode(SECTIONS, MachineConfig::default());
+    let path = env::temp_dir().join(format!("disasm-{}.fasm", std::process::id()));
+    cpu.disassemble(File::create(&path).unwrap());
+    let text = fs::read_to_string(&path).unwrap();
+    fs::remove_file(&path).ok();
+
+    let again = txtparse::parsecode(&text, MachineConfig::default());
+    let size = cpu.state.progsz as usize;
+    assert_eq!(cpu.state.mem[..size], again.state.mem[..size]);
+    assert_eq!((cpu.state.cnst_start, cpu.state.data_start), (again.state.cnst_start, again.state.data_start));
+    assert_eq!(cpu.state.r[15], again.state.r[15]);
+}
@@ -0,0 +1,94 @@
+use assembly::cpu::{Engine, MachineConfig, Outcome, CPU};
+use assembly::txtparse;
+
+mod common;
+use common::SELF_MODIFYING;
+
+/// Sums 1..=10 through a called function, then halts.
+const CALLS: &str = "
+add_to:
+    add r0 r1 0
+    ret 0
+main:
+    lc r0 0
+    lc r1 1
+loop:
+    calli add_to
+    addi r1 1
+    cmpi r1 11
+    jl loop
+    muli r0 2
+    halt r0 0
+end main
+";
+
+/// Counts down from 50 forever, for runs stopped by the step limit.
+const SPIN: &str = "
+main:
+    lc r0 50
+loop:
+    subi r0 1
+    addi r1 3
+    cmpi r0 0
+    jg loop
+    jmp main
+end main
+";
+
+fn cpu(src: &str, engine: Engine) -> CPU {
+    txtparse::parsecode(src, MachineConfig{ engine, ..MachineConfig::default() })
+}
+
+fn assert_same(interp: &CPU, blocks: &CPU) {
+    assert_eq!(interp.state.steps, blocks.state.steps, "steps");
+    assert_eq!(interp.state.cycles, blocks.state.cycles, "cycles");
+    assert_eq!(interp.state.r, blocks.state.r, "registers");
+    assert!(interp.state.mem == blocks.state.mem, "memory");
+}
+
+/// Runs `src` to the end on both engines, returns the interpreter.
+fn cross_check(src: &str) -> CPU {
+    let mut interp = cpu(src, Engine::Interp);
+    let mut blocks = cpu(src, Engine::Blocks);
+    match (interp.exec(), blocks.exec()) {
+        (Outcome::Halted(a), Outcome::Halted(b)) => assert_eq!(a, b),
+        (a, b) => panic!("outcomes differ: {:?} vs {:?}", a, b)
+    }
+    assert_same(&interp, &blocks);
+    interp
+}
+
+#[test]
+fn engines_agree_on_calls() {
+    assert_eq!(cross_check(CALLS).state.r[0], 110);
+}
+
+#[test]
+fn engines_agree_on_self_modifying_code() {
+    assert_eq!(cross_check(SELF_MODIFYING).state.r[0], 101);
+}
+
+#[test]
+fn engines_agree_on_step_limits() {
+    let mut interp = cpu(SPIN, Engine::Interp);
+    let mut blocks = cpu(SPIN, Engine::Blocks);
+    for limit in &[1, 2, 7, 64, 1000] {
+        assert!(matches!(interp.exec_with_limit(*limit), Outcome::StepLimit));
+        assert!(matches!(blocks.exec_with_limit(*limit), Outcome::StepLimit));
+        assert_same(&interp, &blocks);
+    }
+}
+
+#[test]
+fn engines_agree_after_set_cycles() {
+    let mut interp = cpu(SPIN, Engine::Interp);
+    let mut blocks = cpu(SPIN, Engine::Blocks);
+    for cycles in 1..5 {
+        interp.table.set_cycles("addi", cycles);
+        blocks.table.set_cycles("addi", cycles);
+        interp.exec_with_limit(100);
+        blocks.exec_with_limit(100);
+        assert_same(&interp, &blocks);
+    }
+    assert!(interp.state.cycles > interp.state.steps);
+}
@@ -0,0 +1,51 @@
+use assembly::cpu::{CmdTable, MachineConfig, CPU, EXEC_MAGIC, MAX_MEM_WORDS};
+use assembly::{inspect, txtparse};
+use std::{env, fs, fs::File};
+
+/// Bytes of a saved executable whose header asks for `mem_words` words.
+fn exec_asking_for(mem_words: u32, name: &str) -> (std::path::PathBuf, Vec<u8>) {
+    let cpu = txtparse::parsecode("main:\n    halt r0 0\nend main\n", MachineConfig::default());
+    let path = env::temp_dir().join(format!("{}-{}.fbin", name, std::process::id()));
+    cpu.save(&mut File::create(&path).unwrap());
+    let mut bytes = fs::read(&path).unwrap();
+    let at = EXEC_MAGIC.len() + 5 * 4;
+    bytes[at..at + 4].copy_from_slice(&mem_words.to_le_bytes());
+    fs::write(&path, &bytes).unwrap();
+    (path, bytes)
+}
+
+#[test]
+fn inspect_reports_huge_memory() {
+    let (path, bytes) = exec_asking_for(0xFFFF_FFF0, "inspect-mem");
+    fs::remove_file(path).ok();
+    let mut out = Vec::new();
+    inspect::inspect(&bytes, &CmdTable::new(), &mut out);
+    let report = String::from_utf8(out).unwrap();
+    assert!(report.contains(&format!("above the {} words allowed", MAX_MEM_WORDS)), "{}", report);
+}
+
+#[test]
+#[should_panic(expected = "words of memory")]
+fn load_refuses_huge_memory() {
+    let (path, _) = exec_asking_for(0xFFFF_FFF0, "load-mem");
+    let mut f = File::open(&path).unwrap();
+    fs::remove_file(&path).ok();
+    CPU::default().load(&mut f);
+}
+
+/// Two words of code, then a data word.
+const WITH_DATA: &str = "main:\n    lc r0 5\n    halt r0 0\nsection data\n    word 7\nend main\n";
+
+#[test]
+#[should_panic(expected = "outside the code section")]
+fn entry_cannot_move_into_data() {
+    let mut cpu = txtparse::parsecode(WITH_DATA, MachineConfig::default());
+    cpu.patch_entry(2);
+}
+
+#[test]
+fn entry_moves_within_code() {
+    let mut cpu = txtparse::parsecode(WITH_DATA, MachineConfig::default());
+    cpu.patch_entry(1);
+    assert_eq!(cpu.state.r[15], 1);
+}
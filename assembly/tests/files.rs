@@ -0,0 +1,71 @@
+use assembly::cpu::{fmode, Files, Vfs, MAX_MEM_FILE};
+use std::collections::HashMap;
+
+fn memory_files() -> Files {
+    let mut files = HashMap::new();
+    files.insert("hello".to_owned(), b"hello".to_vec());
+    Files::new(Vfs::Memory(files))
+}
+
+fn contents(files: &Files, name: &str) -> Vec<u8> {
+    match &files.vfs {
+        Vfs::Memory(files) => files[name].clone(),
+        _ => unreachable!()
+    }
+}
+
+#[test]
+fn memory_handles_keep_their_mode() {
+    let mut files = memory_files();
+    let read = files.open("hello", fmode::READ).unwrap();
+    assert_eq!(files.write(read, b"XX"), None);
+    assert_eq!(contents(&files, "hello"), b"hello");
+
+    let append = files.open("hello", fmode::APPEND).unwrap();
+    assert_eq!(files.read(append, &mut [0; 4]), None);
+    files.seek(append, 0, 0).unwrap();
+    assert_eq!(files.write(append, b"!"), Some(1));
+    assert_eq!(contents(&files, "hello"), b"hello!");
+}
+
+#[test]
+fn memory_files_are_capped() {
+    let mut files = memory_files();
+    let num = files.open("hello", fmode::UPDATE).unwrap();
+    assert_eq!(files.seek(num, 6442450946, 0), None);
+    assert_eq!(files.seek(num, MAX_MEM_FILE as i64, 0), Some(MAX_MEM_FILE as u64));
+    assert_eq!(files.write(num, b"X"), None);
+    assert_eq!(contents(&files, "hello"), b"hello");
+}
+
+#[cfg(unix)]
+#[test]
+fn host_symlinks_stay_inside_the_root() {
+    use std::{env, fs, os::unix::fs::symlink};
+
+    let dir = env::temp_dir().join(format!("vfs-{}", std::process::id()));
+    let root = dir.join("root");
+    fs::create_dir_all(root.join("sub")).unwrap();
+    fs::write(dir.join("secret"), b"secret").unwrap();
+    fs::write(root.join("sub/inside"), b"inside").unwrap();
+    symlink(dir.join("secret"), root.join("leak")).unwrap();
+    symlink(&dir, root.join("up")).unwrap();
+    symlink(dir.join("missing"), root.join("dangling")).unwrap();
+    symlink(root.join("sub"), root.join("alias")).unwrap();
+
+    let mut files = Files::new(Vfs::Host(root.clone()));
+    assert_eq!(files.open("leak", fmode::READ), None);
+    assert_eq!(files.open("up/secret", fmode::READ), None);
+    assert_eq!(files.open("up/new", fmode::WRITE), None);
+    assert_eq!(files.open("dangling", fmode::WRITE), None);
+    assert!(!dir.join("missing").exists());
+
+    let num = files.open("alias/inside", fmode::READ).unwrap();
+    let mut buf = [0; 6];
+    assert_eq!(files.read(num, &mut buf), Some(6));
+    assert_eq!(&buf, b"inside");
+    assert!(files.open("sub/new", fmode::WRITE).is_some());
+    assert!(root.join("sub/new").exists());
+
+    fs::remove_dir_all(&dir).ok();
+}
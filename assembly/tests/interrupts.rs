@@ -0,0 +1,40 @@
+use assembly::cpu::{Engine, MachineConfig, Outcome, Timer};
+use assembly::txtparse;
+
+/// Installs the handler at address 11 for timer line 3, then waits for
+/// five interrupts. The handler runs with the stack the interrupt left.
+const TIMER: &str = "
+main:
+    syscall r2 4
+    lc r0 11
+    storer r0 r2 3
+    lc r0 100
+    store r0 800002
+    lc r0 3
+    store r0 800003
+    ei r0 0
+wait:
+    cmpi r1 5
+    jl wait
+    halt r0 0
+handler:
+    addi r1 1
+    iret r0 0
+end main
+";
+
+#[test]
+fn timer_interrupts_return_to_the_loop() {
+    let mut steps = Vec::new();
+    for (engine, mem_words) in &[(Engine::Interp, 1 << 20), (Engine::Blocks, 1 << 20), (Engine::Interp, 900000)] {
+        let cfg = MachineConfig{ engine: *engine, mem_words: *mem_words, ..MachineConfig::default() };
+        let mut cpu = txtparse::parsecode(TIMER, cfg);
+        cpu.state.bus.map(800000, Box::new(Timer::default()));
+        let top = cpu.state.r[14];
+        assert!(matches!(cpu.exec_with_limit(10000), Outcome::Halted(0)));
+        assert_eq!(cpu.state.r[1], 5);
+        assert_eq!(cpu.state.r[14], top);
+        steps.push(cpu.state.steps);
+    }
+    assert!(steps.iter().all(|cnt| *cnt == steps[0]));
+}
@@ -0,0 +1,37 @@
+use assembly::cpu::{FaultKind, MachineConfig, Outcome, CPU};
+use assembly::txtparse;
+
+fn run(src: &str) -> (CPU, Outcome) {
+    let mut cpu = txtparse::parsecode(src, MachineConfig::default());
+    cpu.state.set_args(&["prog", "arg"], &["A=1"]);
+    let outcome = cpu.exec_with_limit(1000);
+    (cpu, outcome)
+}
+
+fn assert_fault(outcome: Outcome, kind: FaultKind, pc: u32) {
+    match outcome {
+        Outcome::Fault(fault) => assert_eq!((fault.kind, fault.pc), (kind, pc)),
+        outcome => panic!("expected {:?}, got {:?}", kind, outcome)
+    }
+}
+
+#[test]
+fn pop_on_empty_stack_faults() {
+    let (_, outcome) = run("main:\n    pop r0 0\n    halt r0 0\nend main\n");
+    assert_fault(outcome, FaultKind::StackUnderflow, 0);
+}
+
+#[test]
+fn ret_on_empty_stack_faults() {
+    let (cpu, outcome) = run("main:\n    lc r0 5\n    ret 0\nend main\n");
+    assert_fault(outcome, FaultKind::StackUnderflow, 1);
+    assert_eq!(cpu.state.steps, 2);
+}
+
+#[test]
+fn pushed_values_pop_back() {
+    let src = "main:\n    lc r0 7\n    push r0 0\n    push r0 1\n    pop r1 0\n    pop r2 0\n    pop r3 0\nend main\n";
+    let (cpu, outcome) = run(src);
+    assert_fault(outcome, FaultKind::StackUnderflow, 5);
+    assert_eq!((cpu.state.r[1], cpu.state.r[2]), (8, 7));
+}
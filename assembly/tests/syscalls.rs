@@ -0,0 +1,54 @@
+use assembly::cpu::{CpuState, IoSyscalls, MachineConfig, Outcome, SharedBuf, SyscallHandler, Word};
+use assembly::txtparse;
+use std::io::Cursor;
+
+/// Reads two numbers and prints their sum, then echoes a signed token.
+const ECHO_SUM: &str = "
+main:
+    syscall r0 100
+    syscall r1 100
+    add r0 r1 0
+    syscall r0 102
+    lc r0 10
+    syscall r0 105
+    syscall r2 111
+    syscall r2 107
+    halt r0 0
+end main
+";
+
+#[test]
+fn output_is_captured() {
+    let mut cpu = txtparse::parsecode(ECHO_SUM, MachineConfig::default());
+    let out = SharedBuf::new();
+    cpu.state.syscalls = Box::new(IoSyscalls::new(Cursor::new("40\n2\n  -17 "), out.clone()));
+    assert!(matches!(cpu.exec(), Outcome::Halted(0)));
+    assert_eq!(out.text(), "42\n-17");
+}
+
+/// Serves syscall 7 itself and everything else through `IoSyscalls`.
+struct Doubler<W> {
+    io: IoSyscalls<Cursor<&'static str>, W>
+}
+
+impl<W: std::io::Write> SyscallHandler for Doubler<W> {
+    fn syscall(&mut self, cpu: &mut CpuState, reg: usize, num: Word) -> bool {
+        match num {
+            7 => { cpu.r[reg] *= 2; true }
+            _ => self.io.syscall(cpu, reg, num)
+        }
+    }
+}
+
+#[test]
+fn custom_handler_falls_back_to_io() {
+    let src = "main:\n    syscall r0 100\n    syscall r0 7\n    syscall r0 102\n    syscall r0 9\nend main\n";
+    let mut cpu = txtparse::parsecode(src, MachineConfig::default());
+    let out = SharedBuf::new();
+    cpu.state.syscalls = Box::new(Doubler{ io: IoSyscalls::new(Cursor::new("21\n"), out.clone()) });
+    match cpu.exec() {
+        Outcome::Fault(fault) => assert_eq!(fault.pc, 3),
+        outcome => panic!("expected a fault, got {:?}", outcome)
+    }
+    assert_eq!(out.text(), "42");
+}